@@ -1,43 +1,635 @@
-use std::path::Path;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use nes_emulator::{
-    core::{Bus, Cartridge, Cpu},
-    trace,
+    capture::{AudioRecorder, ImageFormat, ImageOptions, VideoRecorder},
+    core::{
+        opcode::OPCODE_MAP, BuiltinPalette, Cartridge, Palette, Region, PRG_RAM_SIZE, RAM_SIZE,
+    },
+    disasm::disassemble,
+    error::{Error, Result},
+    io::Read,
+    test_rom::{find_test_roms, run_test_rom, TestStatus, DEFAULT_MAX_FRAMES},
+    trace, Nes,
 };
 
-const TEST_DATA_DIRECTORY: &str = "test_data";
+const USAGE: &str = "\
+usage: nes-emulator <command> [options]
 
-fn main() -> nes_emulator::error::Result<()> {
-    let filename = format!("./{TEST_DATA_DIRECTORY}/cpu_dummy_reads.nes");
-    let path = Path::new(&filename);
-    let cartridge = Cartridge::try_from(path)?;
-    // let cartridge = Cartridge::new(vec![
-    // 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-    // 0x00,
-    // ])?;
+commands:
+  run <rom>       run headless for a number of frames or until a condition
+  trace <rom>     log every executed instruction
+  info <rom>      print the cartridge header
+  disasm <rom>    disassemble the program ROM
+  test <dir>      run every test ROM under a directory
+  help            print this message
 
-    let mut nes = Nes::new(&cartridge)?;
-    nes.run()?;
+common options:
+  --region ntsc|pal|dendy      override the region from the header
+  --palette NAME|FILE.pal      2c02, 2c03, 2c05 or a palette file
+  --frames COUNT               stop after COUNT frames, 600 for run by default
+  --output FILE                write text output to FILE instead of stdout
 
-    Ok(())
+run options:
+  --until-pc ADDR              stop when the CPU is about to execute ADDR
+  --until-ram ADDR=VALUE       stop once work RAM or PRG-RAM at ADDR holds VALUE
+  --wav FILE                   record the audio
+  --wav-start FRAME            first frame to record
+  --wav-stop FRAME             frame to stop recording on
+  --stems                      record every channel to its own file as well
+  --y4m FILE                   record video, with the audio in a WAV next to it
+  --screenshot-at FRAME        save a screenshot at FRAME, can be repeated
+  --screenshot-every COUNT     save a screenshot every COUNT frames
+  --screenshot-dir DIR         directory for screenshots
+  --screenshot-format png|ppm  screenshot file format
+  --crop-overscan              drop the 8 rows at the top and bottom of pictures
+  --scale FACTOR               integer scale of pictures
+
+trace options:
+  --instructions COUNT         stop after COUNT instructions
+  --pc START[-END]             only log instructions in this address range
+  --mnemonic NAME              only log this instruction, can be repeated
+
+disasm options:
+  --start ADDR                 first address, $8000 by default
+  --end ADDR                   last address, $FFFF by default
+
+Numbers are decimal, addresses hexadecimal with an optional $ or 0x prefix.";
+
+/// Frames `run` goes through without `--frames`, conditions included
+const DEFAULT_RUN_FRAMES: usize = 600;
+const PROGRAM_ROM_START: u16 = 0x8000;
+const PRG_RAM_START: u16 = 0x6000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Run,
+    Trace,
+    Info,
+    Disasm,
+    Test,
+    Help,
 }
 
-struct Nes(Cpu);
+impl Command {
+    /// Options the command accepts besides the common ones
+    fn options(&self) -> &'static [&'static str] {
+        match self {
+            Self::Run => &[
+                "--until-pc",
+                "--until-ram",
+                "--wav",
+                "--wav-start",
+                "--wav-stop",
+                "--stems",
+                "--y4m",
+                "--screenshot-at",
+                "--screenshot-every",
+                "--screenshot-dir",
+                "--screenshot-format",
+                "--crop-overscan",
+                "--scale",
+            ],
+            Self::Trace => &["--instructions", "--pc", "--mnemonic"],
+            Self::Disasm => &["--start", "--end"],
+            Self::Info | Self::Test | Self::Help => &[],
+        }
+    }
+}
 
-impl Nes {
-    pub fn new(cartridge: &Cartridge) -> nes_emulator::error::Result<Self> {
-        let bus = Bus::new(cartridge);
-        let mut cpu = Cpu::new(bus);
-        cpu.load()?;
+impl FromStr for Command {
+    type Err = Error;
 
-        Ok(Self(cpu))
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "run" => Ok(Self::Run),
+            "trace" => Ok(Self::Trace),
+            "info" => Ok(Self::Info),
+            "disasm" => Ok(Self::Disasm),
+            "test" => Ok(Self::Test),
+            "help" | "--help" | "-h" => Ok(Self::Help),
+            _ => Err(Error::Unsupported(format!(
+                "unknown command: {name}, see `nes-emulator help`"
+            ))),
+        }
     }
+}
 
-    pub fn run(&mut self) -> nes_emulator::error::Result<()> {
-        self.0.run_with_callback(|cpu| {
-            println!("{}", trace(cpu)?);
+/// Command line options, see `USAGE`
+#[derive(Debug)]
+struct Options {
+    command: Command,
+    /// ROM, or test ROM directory for `test`
+    path: PathBuf,
+    region: Option<Region>,
+    palette: Option<Palette>,
+    frames: Option<usize>,
+    output: Option<PathBuf>,
+    until_pc: Option<u16>,
+    until_ram: Option<(u16, u8)>,
+    wav: Option<PathBuf>,
+    wav_start: usize,
+    wav_stop: Option<usize>,
+    stems: bool,
+    /// Video of the whole run, the audio goes to a WAV file next to it
+    y4m: Option<PathBuf>,
+    screenshot_at: Vec<usize>,
+    screenshot_every: Option<usize>,
+    screenshot_dir: Option<PathBuf>,
+    screenshot_format: Option<ImageFormat>,
+    image: ImageOptions,
+    instructions: Option<usize>,
+    pc: Option<(u16, u16)>,
+    mnemonics: Vec<String>,
+    start: Option<u16>,
+    end: Option<u16>,
+}
 
-            Ok(())
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let command = match args.next() {
+            Some(command) => command.parse()?,
+            None => Command::Help,
+        };
+        let mut options = Self {
+            command,
+            path: PathBuf::new(),
+            region: None,
+            palette: None,
+            frames: None,
+            output: None,
+            until_pc: None,
+            until_ram: None,
+            wav: None,
+            wav_start: 0,
+            wav_stop: None,
+            stems: false,
+            y4m: None,
+            screenshot_at: Vec::new(),
+            screenshot_every: None,
+            screenshot_dir: None,
+            screenshot_format: None,
+            image: ImageOptions::default(),
+            instructions: None,
+            pc: None,
+            mnemonics: Vec::new(),
+            start: None,
+            end: None,
+        };
+        let mut path = None;
+
+        while let Some(arg) = args.next() {
+            let common = ["--region", "--palette", "--frames", "--output"];
+            if arg.starts_with("--")
+                && !common.contains(&arg.as_str())
+                && !command.options().contains(&arg.as_str())
+            {
+                return Err(Error::Unsupported(format!(
+                    "unknown option for this command: {arg}"
+                )));
+            }
+
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| Error::Unsupported(format!("{arg} expects a value")))
+            };
+
+            match arg.as_str() {
+                "--region" => options.region = Some(value()?.parse()?),
+                "--palette" => options.palette = Some(parse_palette(&value()?)?),
+                "--frames" => options.frames = Some(parse_number(&value()?)?),
+                "--output" => options.output = Some(value()?.into()),
+                "--until-pc" => options.until_pc = Some(parse_address(&value()?)?),
+                "--until-ram" => {
+                    let value = value()?;
+                    let (address, byte) = value.split_once('=').ok_or_else(|| {
+                        Error::Unsupported(format!("--until-ram expects ADDR=VALUE: {value}"))
+                    })?;
+                    let byte = parse_address(byte)?;
+                    let byte = u8::try_from(byte)
+                        .map_err(|_| Error::Unsupported(format!("not a byte: {byte:#x}")))?;
+                    options.until_ram = Some((parse_address(address)?, byte));
+                }
+                "--wav" => options.wav = Some(value()?.into()),
+                "--wav-start" => options.wav_start = parse_number(&value()?)?,
+                "--wav-stop" => options.wav_stop = Some(parse_number(&value()?)?),
+                "--stems" => options.stems = true,
+                "--y4m" => options.y4m = Some(value()?.into()),
+                "--screenshot-at" => options.screenshot_at.push(parse_number(&value()?)?),
+                "--screenshot-every" => {
+                    options.screenshot_every = Some(parse_number(&value()?)?.max(1));
+                }
+                "--screenshot-dir" => options.screenshot_dir = Some(value()?.into()),
+                "--screenshot-format" => {
+                    let format = value()?;
+                    let format = ImageFormat::from_path(format!("screenshot.{format}"))
+                        .ok_or_else(|| {
+                            Error::Unsupported(format!("unknown screenshot format: {format}"))
+                        })?;
+                    options.screenshot_format = Some(format);
+                }
+                "--crop-overscan" => options.image.crop_overscan = true,
+                "--scale" => options.image.scale = parse_number(&value()?)?.max(1),
+                "--instructions" => options.instructions = Some(parse_number(&value()?)?),
+                "--pc" => {
+                    let value = value()?;
+                    let (start, end) = value.split_once('-').unwrap_or((&value, &value));
+                    options.pc = Some((parse_address(start)?, parse_address(end)?));
+                }
+                "--mnemonic" => options.mnemonics.push(value()?.to_ascii_uppercase()),
+                "--start" => options.start = Some(parse_address(&value()?)?),
+                "--end" => options.end = Some(parse_address(&value()?)?),
+                _ if path.is_none() => path = Some(PathBuf::from(&arg)),
+                _ => return Err(Error::Unsupported(format!("unexpected argument: {arg}"))),
+            }
+        }
+
+        if options.wav.is_some() && options.y4m.is_some() {
+            return Err(Error::Unsupported(
+                "--y4m records its own WAV file, it can't be combined with --wav".to_owned(),
+            ));
+        }
+        match (command, path) {
+            (Command::Help, _) => {}
+            (_, Some(path)) => options.path = path,
+            (Command::Test, None) => {
+                return Err(Error::Unsupported(
+                    "test expects a directory of test ROMs".to_owned(),
+                ))
+            }
+            (_, None) => return Err(Error::Unsupported("expected the path of a ROM".to_owned())),
+        }
+
+        Ok(options)
+    }
+
+    fn build(&self, cartridge: Cartridge) -> Result<Nes> {
+        let mut builder = Nes::builder().cartridge(cartridge);
+        if let Some(region) = self.region {
+            builder = builder.region(region);
+        }
+        if let Some(palette) = &self.palette {
+            builder = builder.palette(palette.clone());
+        }
+
+        builder.build()
+    }
+
+    fn load(&self) -> Result<Nes> {
+        self.build(Cartridge::try_from(self.path.as_path())?)
+    }
+
+    /// Standard output unless `--output` was given
+    fn output(&self) -> Result<Box<dyn Write>> {
+        Ok(match &self.output {
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(std::io::stdout().lock()),
         })
     }
+
+    fn screenshot_due(&self, frame: usize) -> bool {
+        self.screenshot_at.contains(&frame)
+            || self
+                .screenshot_every
+                .is_some_and(|every| frame.is_multiple_of(every))
+    }
+}
+
+fn parse_number(value: &str) -> Result<usize> {
+    value
+        .parse()
+        .map_err(|_| Error::Unsupported(format!("invalid number: {value}")))
+}
+
+fn parse_address(value: &str) -> Result<u16> {
+    let digits = value
+        .strip_prefix('$')
+        .or_else(|| value.strip_prefix("0x"))
+        .unwrap_or(value);
+
+    u16::from_str_radix(digits, 16)
+        .map_err(|_| Error::Unsupported(format!("invalid address: {value}")))
+}
+
+/// A builtin palette name or a `.pal` file
+fn parse_palette(value: &str) -> Result<Palette> {
+    match Path::new(value).is_file() {
+        true => Palette::new(&std::fs::read(value)?),
+        false => Ok(Palette::from(value.parse::<BuiltinPalette>()?)),
+    }
+}
+
+fn main() -> Result<()> {
+    let options = Options::parse(std::env::args().skip(1))?;
+
+    match options.command {
+        Command::Run => run(&options),
+        Command::Trace => trace_rom(&options),
+        Command::Info => info(&options),
+        Command::Disasm => disasm(&options),
+        Command::Test => test(&options),
+        Command::Help => {
+            println!("{USAGE}");
+            Ok(())
+        }
+    }
+}
+
+/// Why a run ended
+#[derive(Debug)]
+enum Stop {
+    Frames,
+    Pc(u16),
+    Ram(u16, u8),
+}
+
+fn run(options: &Options) -> Result<()> {
+    let mut nes = options.load()?;
+    let frames = options
+        .frames
+        .or(options.wav_stop)
+        .unwrap_or(DEFAULT_RUN_FRAMES);
+
+    let mut recorder: Option<AudioRecorder> = None;
+    let mut video = match &options.y4m {
+        Some(path) => Some(VideoRecorder::create(path, &mut nes, options.image)?),
+        None => None,
+    };
+    let mut frame = nes.frame_count();
+
+    let stop = loop {
+        if let Some(pc) = options.until_pc {
+            if nes.cpu().program_counter() == pc {
+                break Stop::Pc(pc);
+            }
+        }
+
+        nes.step_instruction()?;
+
+        if let Some((address, value)) = options.until_ram {
+            if read_ram(&nes, address) == Some(value) {
+                break Stop::Ram(address, value);
+            }
+        }
+
+        if nes.frame_count() == frame {
+            continue;
+        }
+        frame = nes.frame_count();
+
+        if let Some(path) = &options.wav {
+            let apu = nes.bus_mut().apu_mut();
+            match &mut recorder {
+                Some(recorder) => recorder.record(apu)?,
+                None if frame >= options.wav_start
+                    && options.wav_stop.is_none_or(|stop| frame < stop) =>
+                {
+                    // drop whatever was produced before the first recorded frame
+                    apu.take_samples();
+                    recorder = Some(AudioRecorder::create(path, apu, options.stems)?);
+                }
+                None => {
+                    apu.take_samples();
+                }
+            }
+
+            if Some(frame) == options.wav_stop {
+                if let Some(recorder) = recorder.take() {
+                    recorder.finish()?;
+                }
+            }
+        }
+
+        if let Some(video) = &mut video {
+            video.record(&mut nes)?;
+        }
+
+        if options.screenshot_due(frame) {
+            let format = options.screenshot_format.unwrap_or(ImageFormat::Png);
+            let path = options
+                .screenshot_dir
+                .clone()
+                .unwrap_or_default()
+                .join(format!("frame-{frame:06}.{}", format.extension()));
+            nes.screenshot(options.image).save(path)?;
+        }
+
+        if frame >= frames {
+            break Stop::Frames;
+        }
+    };
+
+    if let Some(recorder) = recorder {
+        recorder.finish()?;
+    }
+    if let Some(video) = video {
+        video.finish()?;
+    }
+
+    let reason = match stop {
+        Stop::Frames => "frame limit reached".to_owned(),
+        Stop::Pc(pc) => format!("reached PC ${pc:04x}"),
+        Stop::Ram(address, value) => format!("${address:04x} holds ${value:02x}"),
+    };
+    writeln!(
+        options.output()?,
+        "stopped after {} frames and {} CPU cycles: {reason}",
+        nes.frame_count(),
+        nes.bus().cycles()
+    )?;
+
+    Ok(())
+}
+
+/// Work RAM, with its mirrors, or PRG-RAM, without touching the bus
+fn read_ram(nes: &Nes, address: u16) -> Option<u8> {
+    let bus = nes.bus();
+    match address as usize {
+        address if address < RAM_SIZE * 4 => Some(bus.ram().dump()[address % RAM_SIZE]),
+        _ => {
+            let offset = address.checked_sub(PRG_RAM_START)? as usize;
+            (offset < PRG_RAM_SIZE).then(|| bus.prg_ram()[offset])
+        }
+    }
+}
+
+fn trace_rom(options: &Options) -> Result<()> {
+    let mut nes = options.load()?;
+    let mut output = options.output()?;
+
+    for _ in 0..options.instructions.unwrap_or(usize::MAX) {
+        if options
+            .frames
+            .is_some_and(|frames| nes.frame_count() >= frames)
+        {
+            break;
+        }
+
+        let pc = nes.cpu().program_counter();
+        let in_range = options
+            .pc
+            .is_none_or(|(start, end)| (start..=end).contains(&pc));
+        let mnemonic_matches = options.mnemonics.is_empty() || {
+            let code = nes.bus_mut().read_byte(pc)?;
+            OPCODE_MAP
+                .get(&code)
+                .is_some_and(|opcode| options.mnemonics.iter().any(|m| m == opcode.mnemonic()))
+        };
+        if in_range && mnemonic_matches {
+            writeln!(output, "{}", trace(nes.cpu_mut())?)?;
+        }
+
+        nes.step_instruction()?;
+    }
+
+    Ok(output.flush()?)
+}
+
+fn info(options: &Options) -> Result<()> {
+    let data = std::fs::read(&options.path)?;
+    let cartridge = Cartridge::new(data.clone())?;
+    let kib = |bytes: usize| format!("{} KiB", bytes / 1024);
+    let yes_no = |flag: bool| if flag { "yes" } else { "no" };
+    let md5: String = cartridge
+        .md5()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+
+    let mut output = options.output()?;
+    let format = match data[7] & 0b1100 {
+        0b1000 => "NES 2.0",
+        _ => "iNES",
+    };
+    writeln!(output, "file:          {}", options.path.display())?;
+    writeln!(output, "format:        {format}")?;
+    writeln!(output, "mapper:        {}", cartridge.mapper())?;
+    writeln!(
+        output,
+        "PRG ROM:       {}",
+        kib(cartridge.program_rom().len())
+    )?;
+    writeln!(
+        output,
+        "CHR ROM:       {}",
+        kib(cartridge.character_rom().len())
+    )?;
+    writeln!(output, "mirroring:     {:?}", cartridge.screen_mirroring())?;
+    writeln!(output, "region:        {:?}", cartridge.region())?;
+    writeln!(output, "input devices: {:?}", cartridge.input_devices())?;
+    writeln!(output, "battery:       {}", yes_no(data[6] & 0b10 != 0))?;
+    writeln!(output, "trainer:       {}", yes_no(data[6] & 0b100 != 0))?;
+    writeln!(output, "CRC-32:        {:08x}", cartridge.hash())?;
+    writeln!(output, "MD5:           {md5}")?;
+
+    Ok(output.flush()?)
+}
+
+fn disasm(options: &Options) -> Result<()> {
+    let cartridge = Cartridge::try_from(options.path.as_path())?;
+    let program = cartridge.program_rom().as_ref();
+    // 16K programs are mirrored into both halves of $8000-$FFFF
+    let program = match program.len() {
+        0x4000 => [program, program].concat(),
+        _ => program.to_vec(),
+    };
+
+    let start = options
+        .start
+        .unwrap_or(PROGRAM_ROM_START)
+        .max(PROGRAM_ROM_START);
+    let end = options.end.unwrap_or(0xFFFF).max(start);
+    let range = (start - PROGRAM_ROM_START) as usize..=(end - PROGRAM_ROM_START) as usize;
+    let bytes = program.get(range).ok_or_else(|| {
+        Error::Unsupported(format!(
+            "${start:04x}-${end:04x} is outside the program ROM"
+        ))
+    })?;
+
+    let mut output = options.output()?;
+    for instruction in disassemble(bytes, start) {
+        writeln!(output, "{instruction}")?;
+    }
+
+    Ok(output.flush()?)
+}
+
+fn test(options: &Options) -> Result<()> {
+    let mut output = options.output()?;
+    let max_frames = options.frames.unwrap_or(DEFAULT_MAX_FRAMES);
+    let (mut passed, mut failed) = (0, 0);
+
+    for path in find_test_roms(&options.path)? {
+        let report = Cartridge::try_from(path.as_path())
+            .and_then(|cartridge| options.build(cartridge))
+            .and_then(|mut nes| run_test_rom(&mut nes, max_frames));
+
+        let line = match report {
+            Ok(report) => {
+                match report.status.is_failure() {
+                    true => failed += 1,
+                    false => passed += 1,
+                }
+                let status = match report.status {
+                    TestStatus::Passed => "PASS".to_owned(),
+                    TestStatus::Failed(code) => format!("FAIL {code}"),
+                    TestStatus::TimedOut => "TIMEOUT".to_owned(),
+                    TestStatus::NoProtocol => "NO-PROTOCOL".to_owned(),
+                };
+                let message = report.message.replace('\n', " ");
+                format!("{status:<11} {} {message}", path.display())
+            }
+            Err(error) => {
+                failed += 1;
+                format!("{:<11} {} {error}", "ERROR", path.display())
+            }
+        };
+        writeln!(output, "{}", line.trim_end())?;
+    }
+
+    writeln!(output, "{passed} passed, {failed} failed")?;
+    output.flush()?;
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(Error::Illegal(format!("{failed} test ROMs failed"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_address, Command, Options};
+
+    fn parse(args: &str) -> nes_emulator::error::Result<Options> {
+        Options::parse(args.split_whitespace().map(str::to_owned))
+    }
+
+    #[test]
+    fn subcommands_take_their_own_options() {
+        let options = parse("run game.nes --frames 10 --region pal --until-pc $c000").unwrap();
+        assert_eq!(options.command, Command::Run);
+        assert_eq!(options.frames, Some(10));
+        assert_eq!(options.until_pc, Some(0xC000));
+
+        let options = parse("trace game.nes --pc 8000-80ff --mnemonic jmp").unwrap();
+        assert_eq!(options.pc, Some((0x8000, 0x80FF)));
+        assert_eq!(options.mnemonics, ["JMP"]);
+
+        assert!(parse("info game.nes --until-pc 8000").is_err());
+        assert!(parse("disasm").is_err());
+        assert!(parse("launch game.nes").is_err());
+        assert_eq!(parse("").unwrap().command, Command::Help);
+    }
+
+    #[test]
+    fn addresses_are_hexadecimal() {
+        assert_eq!(parse_address("$6000").unwrap(), 0x6000);
+        assert_eq!(parse_address("0xfffc").unwrap(), 0xFFFC);
+        assert_eq!(parse_address("80").unwrap(), 0x80);
+        assert!(parse_address("zz").is_err());
+    }
 }
@@ -0,0 +1,7 @@
+mod screenshot;
+mod video;
+mod wav;
+
+pub use screenshot::{Image, ImageFormat, ImageOptions, OVERSCAN_ROWS};
+pub use video::{VideoRecorder, Y4mWriter};
+pub use wav::{AudioRecorder, WavWriter};
@@ -0,0 +1,270 @@
+use std::path::Path;
+
+use crate::{
+    core::{Frame, Palette},
+    error::{Error, Result},
+    hash,
+};
+
+/// Rows at the top and bottom of the picture most TVs hide
+pub const OVERSCAN_ROWS: usize = 8;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+/// Largest stored deflate block
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+const ADLER32_MODULUS: u32 = 65_521;
+
+/// How a frame is turned into an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageOptions {
+    /// Drops the `OVERSCAN_ROWS` at the top and bottom
+    pub crop_overscan: bool,
+    /// Integer scale factor, pixels are repeated
+    pub scale: usize,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            crop_overscan: false,
+            scale: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    /// Binary `P6` portable pixmap
+    Ppm,
+}
+
+impl ImageFormat {
+    /// Picks the format from a `.png` or `.ppm` extension
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+
+        match extension.as_str() {
+            "png" => Some(Self::Png),
+            "ppm" => Some(Self::Ppm),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Ppm => "ppm",
+        }
+    }
+}
+
+/// An 8-bit RGB picture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    rgb: Vec<u8>,
+}
+
+impl Image {
+    pub fn from_frame(frame: &Frame, palette: &Palette, options: ImageOptions) -> Self {
+        let scale = options.scale.max(1);
+        let rows = match options.crop_overscan {
+            true => OVERSCAN_ROWS..frame.height() - OVERSCAN_ROWS,
+            false => 0..frame.height(),
+        };
+        let width = frame.width() * scale;
+        let height = rows.len() * scale;
+
+        let mut rgb = Vec::with_capacity(width * height * 3);
+        for y in rows {
+            let row: Vec<u8> = frame
+                .scanline(y)
+                .iter()
+                .flat_map(|&pixel| palette.color(pixel).repeat(scale))
+                .collect();
+            for _ in 0..scale {
+                rgb.extend_from_slice(&row);
+            }
+        }
+
+        Self { width, height, rgb }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Tightly packed rows of RGB bytes
+    pub fn rgb(&self) -> &[u8] {
+        &self.rgb
+    }
+
+    /// Writes the image in the format its extension asks for
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let format = ImageFormat::from_path(path).ok_or_else(|| {
+            Error::Unsupported(format!("unknown image format: {}", path.display()))
+        })?;
+
+        Ok(std::fs::write(path, self.encode(format))?)
+    }
+
+    pub fn encode(&self, format: ImageFormat) -> Vec<u8> {
+        match format {
+            ImageFormat::Png => self.to_png(),
+            ImageFormat::Ppm => self.to_ppm(),
+        }
+    }
+
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut data = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        data.extend_from_slice(&self.rgb);
+
+        data
+    }
+
+    /// Encodes an uncompressed PNG, the pixels go into stored deflate blocks
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(13);
+        header.extend((self.width as u32).to_be_bytes());
+        header.extend((self.height as u32).to_be_bytes());
+        // 8 bits per channel, RGB, deflate, adaptive filtering, no interlacing
+        header.extend([8, 2, 0, 0, 0]);
+
+        // every row starts with filter type 0, none
+        let mut scanlines = Vec::with_capacity((self.width * 3 + 1) * self.height);
+        for row in self.rgb.chunks(self.width * 3) {
+            scanlines.push(0);
+            scanlines.extend_from_slice(row);
+        }
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut png, b"IHDR", &header);
+        write_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines));
+        write_chunk(&mut png, b"IEND", &[]);
+
+        png
+    }
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = hash::crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+/// A zlib stream of stored, uncompressed, deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // deflate with a 32K window, no preset dictionary, header checksum included
+    let mut stream = vec![0x78, 0x01];
+
+    let mut blocks: Vec<&[u8]> = data.chunks(MAX_STORED_BLOCK).collect();
+    if blocks.is_empty() {
+        blocks.push(&[]);
+    }
+    let count = blocks.len();
+    for (index, block) in blocks.into_iter().enumerate() {
+        let last = (index + 1 == count) as u8;
+        let len = block.len() as u16;
+
+        stream.push(last);
+        stream.extend(len.to_le_bytes());
+        stream.extend((!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend(adler32(data).to_be_bytes());
+
+    stream
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1_u32, 0_u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % ADLER32_MODULUS;
+        (a, (b + a) % ADLER32_MODULUS)
+    });
+
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adler32, zlib_stored, Image, ImageFormat, ImageOptions, OVERSCAN_ROWS};
+    use crate::core::{BuiltinPalette, Frame, Palette, FRAME_HEIGHT, FRAME_WIDTH};
+
+    #[test]
+    fn frames_are_cropped_and_scaled() {
+        let mut frame = Frame::default();
+        frame.set_pixel(0, OVERSCAN_ROWS, 0x30);
+        let palette = Palette::from(BuiltinPalette::default());
+
+        let options = ImageOptions {
+            crop_overscan: true,
+            scale: 2,
+        };
+        let image = Image::from_frame(&frame, &palette, options);
+        assert_eq!(image.width(), FRAME_WIDTH * 2);
+        assert_eq!(image.height(), (FRAME_HEIGHT - 2 * OVERSCAN_ROWS) * 2);
+
+        let white = palette.color(0x30);
+        let row = image.width() * 3;
+        assert_eq!(image.rgb()[..3], white);
+        assert_eq!(image.rgb()[3..6], white);
+        assert_eq!(image.rgb()[row..row + 3], white);
+        assert_ne!(image.rgb()[6..9], white);
+    }
+
+    #[test]
+    fn ppm_has_a_p6_header() {
+        let image = Image::from_frame(
+            &Frame::default(),
+            &Palette::from(BuiltinPalette::default()),
+            ImageOptions::default(),
+        );
+        let ppm = image.to_ppm();
+
+        assert!(ppm.starts_with(b"P6\n256 240\n255\n"));
+        assert_eq!(ppm.len(), 15 + FRAME_WIDTH * FRAME_HEIGHT * 3);
+    }
+
+    #[test]
+    fn png_chunks_are_well_formed() {
+        let image = Image::from_frame(
+            &Frame::default(),
+            &Palette::from(BuiltinPalette::default()),
+            ImageOptions::default(),
+        );
+        let png = image.to_png();
+
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1A\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..20], &256_u32.to_be_bytes());
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+        assert_eq!(ImageFormat::from_path("shot.PNG"), Some(ImageFormat::Png));
+    }
+
+    #[test]
+    fn stored_zlib_streams_split_into_blocks() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+
+        let data = vec![7; 0x1_0000];
+        let stream = zlib_stored(&data);
+        assert_eq!(stream.len(), 2 + 2 * 5 + data.len() + 4);
+        assert_eq!(stream[2], 0);
+        assert_eq!(stream[2 + 5 + 0xFFFF], 1);
+
+        assert_eq!(
+            zlib_stored(&[]),
+            [0x78, 0x01, 1, 0, 0, 0xFF, 0xFF, 0, 0, 0, 1]
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use super::{Image, ImageOptions, WavWriter};
+use crate::{error::Result, Nes};
+
+/// Frame rates are written as a fraction over this denominator
+const FRAME_RATE_DENOMINATOR: u32 = 10_000;
+
+/// Streams pictures into a YUV4MPEG2 file, 4:2:0 with BT.601 studio range colors
+#[derive(Debug)]
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    width: usize,
+    height: usize,
+}
+
+impl Y4mWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        width: usize,
+        height: usize,
+        frame_rate: f64,
+    ) -> Result<Self> {
+        Self::new(
+            BufWriter::new(File::create(path)?),
+            width,
+            height,
+            frame_rate,
+        )
+    }
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Writes the stream header, `width` and `height` have to be even
+    pub fn new(mut writer: W, width: usize, height: usize, frame_rate: f64) -> Result<Self> {
+        let (numerator, denominator) = frame_rate_fraction(frame_rate);
+        writeln!(
+            writer,
+            "YUV4MPEG2 W{width} H{height} F{numerator}:{denominator} Ip A1:1 C420jpeg"
+        )?;
+
+        Ok(Self {
+            writer,
+            width,
+            height,
+        })
+    }
+
+    /// Appends a picture of the size given to `new`
+    pub fn write_frame(&mut self, image: &Image) -> Result<()> {
+        debug_assert_eq!((image.width(), image.height()), (self.width, self.height));
+
+        let rgb = image.rgb();
+        let pixel = |x: usize, y: usize| {
+            let offset = (y * self.width + x) * 3;
+            [
+                rgb[offset] as i32,
+                rgb[offset + 1] as i32,
+                rgb[offset + 2] as i32,
+            ]
+        };
+
+        let luma: Vec<u8> = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (y, x)))
+            .map(|(y, x)| {
+                let [r, g, b] = pixel(x, y);
+                (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8
+            })
+            .collect();
+
+        // chroma of every 2x2 block, from the average of its pixels
+        let (mut cb, mut cr) = (Vec::new(), Vec::new());
+        for y in (0..self.height).step_by(2) {
+            for x in (0..self.width).step_by(2) {
+                let [r, g, b] = [
+                    pixel(x, y),
+                    pixel(x + 1, y),
+                    pixel(x, y + 1),
+                    pixel(x + 1, y + 1),
+                ]
+                .iter()
+                .fold([0; 3], |sum, color| {
+                    [sum[0] + color[0], sum[1] + color[1], sum[2] + color[2]]
+                })
+                .map(|channel| channel / 4);
+
+                cb.push((((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8);
+                cr.push((((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8);
+            }
+        }
+
+        self.writer.write_all(b"FRAME\n")?;
+        self.writer.write_all(&luma)?;
+        self.writer.write_all(&cb)?;
+        self.writer.write_all(&cr)?;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+/// Records the picture to a `.y4m` file and the sound to a `.wav` file next to it.
+/// The audio is kept to exactly the length of the video, at the region's frame rate.
+#[derive(Debug)]
+pub struct VideoRecorder {
+    video: Y4mWriter<BufWriter<File>>,
+    audio: WavWriter<BufWriter<File>>,
+    options: ImageOptions,
+    frame_rate: f64,
+    sample_rate: u32,
+    frames: u64,
+    samples: u64,
+}
+
+impl VideoRecorder {
+    /// Creates `path` and a WAV file with the same name, the audio produced so far
+    /// is dropped
+    pub fn create<P: AsRef<Path>>(path: P, nes: &mut Nes, options: ImageOptions) -> Result<Self> {
+        let path = path.as_ref();
+        let image = nes.screenshot(options);
+        let frame_rate = nes.region().frame_rate();
+        let sample_rate = nes.sample_rate();
+        nes.audio_samples();
+
+        Ok(Self {
+            video: Y4mWriter::create(path, image.width(), image.height(), frame_rate)?,
+            audio: WavWriter::create(path.with_extension("wav"), sample_rate)?,
+            options,
+            frame_rate,
+            sample_rate,
+            frames: 0,
+            samples: 0,
+        })
+    }
+
+    /// Adds the frame `nes` just finished and the audio produced since the last call
+    pub fn record(&mut self, nes: &mut Nes) -> Result<()> {
+        self.video.write_frame(&nes.screenshot(self.options))?;
+        self.frames += 1;
+
+        // the APU runs off the same clock as the picture, so this only ever pads or
+        // drops the odd sample
+        let expected = (self.frames as f64 * self.sample_rate as f64 / self.frame_rate).round();
+        let mut samples = nes.bus_mut().apu_mut().take_samples_i16();
+        samples.resize((expected as u64).saturating_sub(self.samples) as usize, 0);
+        self.audio.write_samples(&samples)?;
+        self.samples += samples.len() as u64;
+
+        Ok(())
+    }
+
+    /// Number of frames recorded
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn finish(self) -> Result<()> {
+        self.video.finish()?;
+        self.audio.finish()?;
+
+        Ok(())
+    }
+}
+
+/// `frame_rate` as a reduced fraction
+fn frame_rate_fraction(frame_rate: f64) -> (u32, u32) {
+    let numerator = (frame_rate * FRAME_RATE_DENOMINATOR as f64).round() as u32;
+    let divisor = gcd(numerator, FRAME_RATE_DENOMINATOR);
+
+    (numerator / divisor, FRAME_RATE_DENOMINATOR / divisor)
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{frame_rate_fraction, Y4mWriter};
+    use crate::{
+        capture::{Image, ImageOptions},
+        core::{BuiltinPalette, Frame, Palette, Region},
+    };
+
+    #[test]
+    fn frame_rates_match_the_region() {
+        assert_eq!(
+            frame_rate_fraction(Region::Ntsc.frame_rate()),
+            (150_247, 2_500)
+        );
+        assert_eq!(
+            frame_rate_fraction(Region::Pal.frame_rate()),
+            (50_007, 1_000)
+        );
+        assert_eq!(
+            frame_rate_fraction(Region::Dendy.frame_rate()),
+            (50_007, 1_000)
+        );
+    }
+
+    #[test]
+    fn frames_are_written_as_420_planes() {
+        let palette = Palette::from(BuiltinPalette::default());
+        let mut frame = Frame::default();
+        for y in 0..frame.height() {
+            for x in 0..frame.width() {
+                frame.set_pixel(x, y, 0x30);
+            }
+        }
+        let image = Image::from_frame(&frame, &palette, ImageOptions::default());
+
+        let mut y4m = Y4mWriter::new(Vec::new(), 256, 240, Region::Pal.frame_rate()).unwrap();
+        y4m.write_frame(&image).unwrap();
+        let bytes = y4m.finish().unwrap();
+
+        let header = b"YUV4MPEG2 W256 H240 F50007:1000 Ip A1:1 C420jpeg\nFRAME\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(bytes.len(), header.len() + 256 * 240 * 3 / 2);
+
+        let [r, g, b] = palette.color(0x30).map(|channel| channel as i32);
+        let luma = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+        assert!(bytes[header.len()..header.len() + 256 * 240]
+            .iter()
+            .all(|&y| y == luma));
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    core::apu::{to_i16, Apu, Channel},
+    error::{Error, Result},
+};
+
+const HEADER_SIZE: u32 = 44;
+const BITS_PER_SAMPLE: u16 = 16;
+/// Largest data chunk the 32-bit RIFF size fields can describe
+const MAX_DATA_SIZE: u32 = u32::MAX - (HEADER_SIZE - 8);
+
+/// Streams mono 16-bit PCM into a RIFF WAVE container, the chunk sizes are filled
+/// in once the writer is finished
+#[derive(Debug)]
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    sample_rate: u32,
+    data_size: u32,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(writer: W, sample_rate: u32) -> Result<Self> {
+        let mut wav = Self {
+            writer,
+            sample_rate,
+            data_size: 0,
+        };
+        wav.write_header()?;
+
+        Ok(wav)
+    }
+
+    /// Appends `samples`, failing without writing anything once the file would grow
+    /// past the 4 GiB a RIFF header can describe
+    pub fn write_samples(&mut self, samples: &[i16]) -> Result<()> {
+        let data_size = u32::try_from(samples.len() * 2)
+            .ok()
+            .and_then(|size| self.data_size.checked_add(size))
+            .filter(|&size| size <= MAX_DATA_SIZE)
+            .ok_or_else(|| Error::Unsupported("WAV files are limited to 4 GiB".to_owned()))?;
+
+        for sample in samples {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_size = data_size;
+
+        Ok(())
+    }
+
+    /// Patches the header with the final sizes and hands back the inner writer
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+
+    fn write_header(&mut self) -> Result<()> {
+        let block_align = BITS_PER_SAMPLE / 8;
+        let byte_rate = self.sample_rate * block_align as u32;
+
+        let w = &mut self.writer;
+        w.write_all(b"RIFF")?;
+        w.write_all(&(HEADER_SIZE - 8 + self.data_size).to_le_bytes())?;
+        w.write_all(b"WAVE")?;
+        w.write_all(b"fmt ")?;
+        w.write_all(&16u32.to_le_bytes())?;
+        // PCM, mono
+        w.write_all(&1u16.to_le_bytes())?;
+        w.write_all(&1u16.to_le_bytes())?;
+        w.write_all(&self.sample_rate.to_le_bytes())?;
+        w.write_all(&byte_rate.to_le_bytes())?;
+        w.write_all(&block_align.to_le_bytes())?;
+        w.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+        w.write_all(b"data")?;
+        w.write_all(&self.data_size.to_le_bytes())?;
+
+        Ok(())
+    }
+}
+
+/// Records the APU's mixed output, and optionally one stem per channel, to `.wav`
+/// files. Stems are written next to the main file as `<name>.<channel>.wav`.
+#[derive(Debug)]
+pub struct AudioRecorder {
+    mix: WavWriter<BufWriter<File>>,
+    stems: Vec<(Channel, WavWriter<BufWriter<File>>)>,
+}
+
+impl AudioRecorder {
+    /// Creates the output files and configures the APU to produce what they need
+    pub fn create<P: AsRef<Path>>(path: P, apu: &mut Apu, stems: bool) -> Result<Self> {
+        let path = path.as_ref();
+        let sample_rate = apu.sample_rate();
+
+        apu.set_stems_enabled(stems);
+        let stems = match stems {
+            true => Channel::ALL
+                .iter()
+                .map(|&channel| {
+                    let writer = WavWriter::create(stem_path(path, channel), sample_rate)?;
+                    Ok((channel, writer))
+                })
+                .collect::<Result<Vec<_>>>()?,
+            false => Vec::new(),
+        };
+
+        Ok(Self {
+            mix: WavWriter::create(path, sample_rate)?,
+            stems,
+        })
+    }
+
+    /// Drains the samples the APU produced since the previous call into the files
+    pub fn record(&mut self, apu: &mut Apu) -> Result<()> {
+        self.mix.write_samples(&apu.take_samples_i16())?;
+        for (channel, writer) in &mut self.stems {
+            writer.write_samples(&to_i16(apu.take_stem_samples(*channel)))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<()> {
+        self.mix.finish()?;
+        for (_, writer) in self.stems {
+            writer.finish()?;
+        }
+
+        Ok(())
+    }
+}
+
+fn stem_path(path: &Path, channel: Channel) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    path.with_file_name(format!("{stem}.{}.wav", channel.name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{stem_path, WavWriter, MAX_DATA_SIZE};
+    use crate::core::apu::Channel;
+    use std::{io::Cursor, path::Path};
+
+    #[test]
+    fn header_sizes_are_patched_on_finish() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 44_100).unwrap();
+        wav.write_samples(&[0, 1, -1]).unwrap();
+        let bytes = wav.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 6);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 42);
+        assert_eq!(
+            u32::from_le_bytes(bytes[24..28].try_into().unwrap()),
+            44_100
+        );
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 6);
+        assert_eq!(&bytes[44..], &[0, 0, 1, 0, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn writes_past_the_riff_size_limit_fail() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 44_100).unwrap();
+        wav.data_size = MAX_DATA_SIZE - 2;
+
+        assert!(wav.write_samples(&[0, 0]).is_err());
+        wav.write_samples(&[0]).unwrap();
+        assert_eq!(wav.data_size, MAX_DATA_SIZE);
+        assert!(wav.write_samples(&[0]).is_err());
+
+        let bytes = wav.finish().unwrap().into_inner();
+        assert_eq!(
+            u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            u32::MAX
+        );
+    }
+
+    #[test]
+    fn stems_are_named_after_their_channel() {
+        assert_eq!(
+            stem_path(Path::new("out/audio.wav"), Channel::Triangle),
+            Path::new("out/audio.triangle.wav")
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use std::f64::consts::PI;
+
+/// Width in output samples of the band-limited step kernel
+const KERNEL_WIDTH: usize = 16;
+/// Number of sub-sample positions the kernel is precomputed for
+const KERNEL_PHASES: usize = 32;
+
+lazy_static! {
+    /// Blackman windowed sinc impulses, one per sub-sample phase, each summing to 1
+    static ref KERNEL: Vec<[f32; KERNEL_WIDTH]> = (0..KERNEL_PHASES)
+        .map(|phase| {
+            let shift = phase as f64 / KERNEL_PHASES as f64;
+            let mut taps = [0.0; KERNEL_WIDTH];
+            for (tap, value) in taps.iter_mut().enumerate() {
+                let x = tap as f64 - (KERNEL_WIDTH / 2) as f64 - shift;
+                let sinc = match x == 0.0 {
+                    true => 1.0,
+                    false => (PI * x).sin() / (PI * x),
+                };
+                let n = (x + (KERNEL_WIDTH / 2) as f64) / KERNEL_WIDTH as f64;
+                let window = 0.42 - 0.5 * (2.0 * PI * n).cos() + 0.08 * (4.0 * PI * n).cos();
+                *value = sinc * window;
+            }
+
+            let sum = taps.iter().sum::<f64>();
+            taps.map(|value| (value / sum) as f32)
+        })
+        .collect();
+}
+
+/// Band-limited resampler in the spirit of blip_buf. Amplitude changes are recorded
+/// as deltas at their clock time and spread over the output samples around it, so
+/// the host rate stream has no aliasing from the square edges of the channels.
+#[derive(Debug, Clone)]
+pub struct BlipBuffer {
+    /// Output samples per input clock
+    factor: f64,
+    /// Position of the current frame's first clock, in output samples
+    frame_start: f64,
+    deltas: Vec<f32>,
+    integrator: f32,
+}
+
+impl BlipBuffer {
+    pub fn new(clock_rate: f64, sample_rate: f64) -> Self {
+        Self {
+            factor: sample_rate / clock_rate,
+            frame_start: 0.0,
+            deltas: vec![0.0; KERNEL_WIDTH],
+            integrator: 0.0,
+        }
+    }
+
+    pub fn set_rates(&mut self, clock_rate: f64, sample_rate: f64) {
+        self.factor = sample_rate / clock_rate;
+    }
+
+    /// Records a change in amplitude `clock` clocks into the current frame
+    pub fn add_delta(&mut self, clock: usize, delta: f32) {
+        let position = self.frame_start + clock as f64 * self.factor;
+        let index = position as usize;
+        let phase = ((position - index as f64) * KERNEL_PHASES as f64) as usize;
+
+        if self.deltas.len() < index + KERNEL_WIDTH {
+            self.deltas.resize(index + KERNEL_WIDTH, 0.0);
+        }
+
+        let kernel = &KERNEL[phase.min(KERNEL_PHASES - 1)];
+        self.deltas[index..index + KERNEL_WIDTH]
+            .iter_mut()
+            .zip(kernel)
+            .for_each(|(sample, tap)| *sample += delta * tap);
+    }
+
+    /// Ends the current frame after `clocks` clocks, making the samples before it
+    /// available for reading
+    pub fn end_frame(&mut self, clocks: usize) {
+        self.frame_start += clocks as f64 * self.factor;
+    }
+
+    pub fn samples_available(&self) -> usize {
+        self.frame_start as usize
+    }
+
+    /// Integrates the finished samples into `out`
+    pub fn read_samples(&mut self, out: &mut Vec<f32>) {
+        let count = self.samples_available();
+        if self.deltas.len() < count + KERNEL_WIDTH {
+            self.deltas.resize(count + KERNEL_WIDTH, 0.0);
+        }
+
+        out.extend(self.deltas.drain(..count).map(|delta| {
+            self.integrator += delta;
+            self.integrator
+        }));
+
+        self.deltas.resize(self.deltas.len() + count, 0.0);
+        self.frame_start -= count as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BlipBuffer;
+
+    #[test]
+    fn a_step_settles_at_its_amplitude() {
+        let mut blip = BlipBuffer::new(1_789_773.0, 44_100.0);
+        blip.add_delta(100, 0.5);
+        blip.end_frame(29_830);
+
+        let mut samples = Vec::new();
+        blip.read_samples(&mut samples);
+
+        assert_eq!(samples.len(), 735);
+        assert!(samples[0].abs() < 0.01);
+        assert!((samples[samples.len() - 1] - 0.5).abs() < 0.001);
+    }
+}
@@ -0,0 +1,201 @@
+use crate::{core::Region, state::stateful};
+
+const SAMPLE_ADDRESS_BASE: u16 = 0xC000;
+
+/// Delta modulation channel, `$4010-$4013`. Plays 1-bit delta encoded samples that
+/// it fetches from CPU memory through DMA.
+#[derive(Debug, Clone)]
+pub struct Dmc {
+    rates: &'static [u16; 16],
+    irq_enabled: bool,
+    irq: bool,
+    looping: bool,
+    timer_period: u16,
+    timer: u16,
+
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+    output_level: u8,
+}
+
+impl Dmc {
+    pub fn new(region: Region) -> Self {
+        let rates = region.dmc_rates();
+
+        Self {
+            rates,
+            irq_enabled: false,
+            irq: false,
+            looping: false,
+            timer_period: rates[0],
+            timer: 0,
+            sample_address: SAMPLE_ADDRESS_BASE,
+            sample_length: 1,
+            current_address: SAMPLE_ADDRESS_BASE,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            output_level: 0,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.rates = region.dmc_rates();
+    }
+
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register & 0b11 {
+            0 => {
+                self.irq_enabled = value & 0b1000_0000 != 0;
+                self.looping = value & 0b0100_0000 != 0;
+                self.timer_period = self.rates[(value & 0b1111) as usize];
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            1 => self.output_level = value & 0b0111_1111,
+            2 => self.sample_address = SAMPLE_ADDRESS_BASE + value as u16 * 64,
+            _ => self.sample_length = value as u16 * 16 + 1,
+        }
+    }
+
+    /// Handles the DMC bit of `$4015`, which also acknowledges the DMC IRQ
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+
+        match enabled {
+            true if self.bytes_remaining == 0 => self.restart(),
+            true => {}
+            false => self.bytes_remaining = 0,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Address the memory reader wants fetched, set whenever the sample buffer runs
+    /// empty while bytes of the sample remain
+    pub fn dma_request(&self) -> Option<u16> {
+        match self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            true => Some(self.current_address),
+            false => None,
+        }
+    }
+
+    /// Hands the byte fetched by DMA to the memory reader
+    pub fn fill_sample_buffer(&mut self, byte: u8) {
+        self.sample_buffer = Some(byte);
+        self.current_address = match self.current_address {
+            0xFFFF => 0x8000,
+            address => address + 1,
+        };
+
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            match (self.looping, self.irq_enabled) {
+                (true, _) => self.restart(),
+                (false, true) => self.irq = true,
+                (false, false) => {}
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle, the rate table is expressed in CPU cycles
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            match self.shift_register & 1 {
+                1 if self.output_level <= 125 => self.output_level += 2,
+                0 if self.output_level >= 2 => self.output_level -= 2,
+                _ => {}
+            }
+        }
+
+        self.shift_register >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(byte) => {
+                    self.silence = false;
+                    self.shift_register = byte;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+}
+
+// the rate table follows the region and is restored with it
+stateful!(Dmc {
+    irq_enabled,
+    irq,
+    looping,
+    timer_period,
+    timer,
+    sample_address,
+    sample_length,
+    current_address,
+    bytes_remaining,
+    sample_buffer,
+    shift_register,
+    bits_remaining,
+    silence,
+    output_level,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::Dmc;
+    use crate::core::Region;
+
+    #[test]
+    fn sample_fetches_raise_the_irq_at_the_end() {
+        let mut dmc = Dmc::new(Region::Ntsc);
+        dmc.write_register(0, 0b1000_0000);
+        dmc.write_register(2, 0xFF);
+        dmc.write_register(3, 0x01);
+        dmc.set_enabled(true);
+
+        assert_eq!(dmc.dma_request(), Some(0xFFC0));
+
+        for _ in 0..17 {
+            let address = dmc.dma_request().unwrap();
+            dmc.fill_sample_buffer(address as u8);
+            dmc.sample_buffer = None;
+        }
+
+        assert_eq!(dmc.dma_request(), None);
+        assert!(!dmc.is_active());
+        assert!(dmc.irq());
+    }
+}
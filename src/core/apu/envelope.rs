@@ -0,0 +1,64 @@
+use crate::state::stateful;
+
+/// Generates a decaying volume, or a constant one, for the pulse and noise channels
+#[derive(Debug, Default, Clone)]
+pub struct Envelope {
+    start: bool,
+    looping: bool,
+    constant: bool,
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    /// Handles the `--LC VVVV` bits shared by `$4000`, `$4004` and `$400C`
+    pub fn write(&mut self, value: u8) {
+        self.looping = value & 0b0010_0000 != 0;
+        self.constant = value & 0b0001_0000 != 0;
+        self.volume = value & 0b1111;
+    }
+
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    /// Clocked by the frame counter on quarter frames
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+
+            return;
+        }
+
+        match self.divider {
+            0 => {
+                self.divider = self.volume;
+                match self.decay {
+                    0 if self.looping => self.decay = 15,
+                    0 => {}
+                    _ => self.decay -= 1,
+                }
+            }
+            _ => self.divider -= 1,
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        match self.constant {
+            true => self.volume,
+            false => self.decay,
+        }
+    }
+}
+
+stateful!(Envelope {
+    start,
+    looping,
+    constant,
+    volume,
+    divider,
+    decay,
+});
@@ -0,0 +1,106 @@
+use std::f32::consts::PI;
+
+/// First-order filter, the building block of the console's analog output stage
+#[derive(Debug, Clone)]
+pub struct OnePole {
+    kind: Kind,
+    cutoff: f32,
+    alpha: f32,
+    previous_input: f32,
+    previous_output: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    HighPass,
+    LowPass,
+}
+
+impl OnePole {
+    pub fn high_pass(cutoff: f32, sample_rate: f32) -> Self {
+        Self::new(Kind::HighPass, cutoff, sample_rate)
+    }
+
+    pub fn low_pass(cutoff: f32, sample_rate: f32) -> Self {
+        Self::new(Kind::LowPass, cutoff, sample_rate)
+    }
+
+    fn new(kind: Kind, cutoff: f32, sample_rate: f32) -> Self {
+        let mut filter = Self {
+            kind,
+            cutoff,
+            alpha: 0.0,
+            previous_input: 0.0,
+            previous_output: 0.0,
+        };
+        filter.set_sample_rate(sample_rate);
+
+        filter
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        let rc = 1.0 / (2.0 * PI * self.cutoff);
+        let dt = 1.0 / sample_rate;
+
+        self.alpha = match self.kind {
+            Kind::HighPass => rc / (rc + dt),
+            Kind::LowPass => dt / (rc + dt),
+        };
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let output = match self.kind {
+            Kind::HighPass => self.alpha * (self.previous_output + input - self.previous_input),
+            Kind::LowPass => self.previous_output + self.alpha * (input - self.previous_output),
+        };
+
+        self.previous_input = input;
+        self.previous_output = output;
+
+        output
+    }
+}
+
+/// The NES output chain: two high-pass filters at 90Hz and 440Hz followed by a
+/// low-pass filter at 14kHz
+#[derive(Debug, Clone)]
+pub struct FilterChain {
+    filters: [OnePole; 3],
+}
+
+impl FilterChain {
+    pub fn new(sample_rate: f32) -> Self {
+        Self {
+            filters: [
+                OnePole::high_pass(90.0, sample_rate),
+                OnePole::high_pass(440.0, sample_rate),
+                OnePole::low_pass(14_000.0, sample_rate),
+            ],
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.filters
+            .iter_mut()
+            .for_each(|filter| filter.set_sample_rate(sample_rate));
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        self.filters
+            .iter_mut()
+            .fold(input, |sample, filter| filter.process(sample))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FilterChain;
+
+    #[test]
+    fn dc_offset_is_removed() {
+        let mut chain = FilterChain::new(44_100.0);
+        let last = (0..44_100).map(|_| chain.process(0.5)).last().unwrap();
+
+        assert!(last.abs() < 0.001);
+    }
+}
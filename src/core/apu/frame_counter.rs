@@ -0,0 +1,191 @@
+use crate::{
+    core::Region,
+    error::Result,
+    state::{invalid, stateful, StateReader, StateWriter, Stateful},
+};
+
+/// Units to clock on a given CPU cycle
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameClock {
+    pub quarter_frame: bool,
+    pub half_frame: bool,
+}
+
+impl FrameClock {
+    const QUARTER: Self = Self {
+        quarter_frame: true,
+        half_frame: false,
+    };
+
+    const HALF: Self = Self {
+        quarter_frame: true,
+        half_frame: true,
+    };
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SequencerMode {
+    #[default]
+    FourStep,
+    FiveStep,
+}
+
+/// Sequencer clocking the envelopes, length counters and sweep units, controlled
+/// through `$4017`
+#[derive(Debug, Clone)]
+pub struct FrameCounter {
+    steps: [usize; 5],
+    mode: SequencerMode,
+    irq_inhibit: bool,
+    irq: bool,
+    cycle: usize,
+    /// CPU cycles left until a `$4017` write resets the sequencer
+    pending_reset: Option<u8>,
+}
+
+impl FrameCounter {
+    pub fn new(region: Region) -> Self {
+        Self {
+            steps: region.frame_counter_steps(),
+            mode: SequencerMode::default(),
+            irq_inhibit: false,
+            irq: false,
+            cycle: 0,
+            pending_reset: None,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.steps = region.frame_counter_steps();
+    }
+
+    /// Handles `$4017` writes, the sequencer restarts 3 or 4 CPU cycles later
+    /// depending on the alignment of the write
+    pub fn write(&mut self, value: u8, cpu_cycles: usize) {
+        self.mode = match value & 0b1000_0000 != 0 {
+            true => SequencerMode::FiveStep,
+            false => SequencerMode::FourStep,
+        };
+
+        self.irq_inhibit = value & 0b0100_0000 != 0;
+        if self.irq_inhibit {
+            self.irq = false;
+        }
+
+        self.pending_reset = Some(3 + (cpu_cycles & 1) as u8);
+    }
+
+    pub fn irq(&self) -> bool {
+        self.irq
+    }
+
+    pub fn acknowledge_irq(&mut self) {
+        self.irq = false;
+    }
+
+    /// Advances the sequencer by a single CPU cycle
+    pub fn clock(&mut self) -> FrameClock {
+        if let Some(delay) = self.pending_reset {
+            match delay {
+                0 => {
+                    self.pending_reset = None;
+                    self.cycle = 0;
+
+                    // entering 5-step mode clocks every unit immediately
+                    if self.mode == SequencerMode::FiveStep {
+                        return FrameClock::HALF;
+                    }
+                }
+                _ => self.pending_reset = Some(delay - 1),
+            }
+        }
+
+        self.cycle += 1;
+
+        let [first, second, third, fourth, fifth] = self.steps;
+        match self.mode {
+            SequencerMode::FourStep => {
+                if (fourth - 1..=fourth + 1).contains(&self.cycle) && !self.irq_inhibit {
+                    self.irq = true;
+                }
+
+                match self.cycle {
+                    cycle if cycle == first || cycle == third => FrameClock::QUARTER,
+                    cycle if cycle == second || cycle == fourth => FrameClock::HALF,
+                    cycle if cycle > fourth => {
+                        self.cycle = 0;
+                        FrameClock::default()
+                    }
+                    _ => FrameClock::default(),
+                }
+            }
+            SequencerMode::FiveStep => match self.cycle {
+                cycle if cycle == first || cycle == third => FrameClock::QUARTER,
+                cycle if cycle == second || cycle == fifth => FrameClock::HALF,
+                cycle if cycle > fifth => {
+                    self.cycle = 0;
+                    FrameClock::default()
+                }
+                _ => FrameClock::default(),
+            },
+        }
+    }
+}
+
+impl Stateful for SequencerMode {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(*self as u8);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        *self = match reader.read_u8()? {
+            0 => Self::FourStep,
+            1 => Self::FiveStep,
+            value => return Err(invalid("frame counter mode", value)),
+        };
+
+        Ok(())
+    }
+}
+
+// the step table follows the region and is restored with it
+stateful!(FrameCounter {
+    mode,
+    irq_inhibit,
+    irq,
+    cycle,
+    pending_reset,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameClock, FrameCounter};
+    use crate::core::Region;
+
+    #[test]
+    fn four_step_mode_raises_the_frame_irq() {
+        let mut frame_counter = FrameCounter::new(Region::Ntsc);
+        (0..29830).for_each(|_| {
+            frame_counter.clock();
+        });
+
+        assert!(frame_counter.irq());
+
+        frame_counter.write(0b0100_0000, 0);
+        assert!(!frame_counter.irq());
+    }
+
+    #[test]
+    fn five_step_mode_clocks_immediately_and_never_interrupts() {
+        let mut frame_counter = FrameCounter::new(Region::Ntsc);
+        frame_counter.write(0b1000_0000, 0);
+
+        let clocks = (0..40000)
+            .map(|_| frame_counter.clock())
+            .filter(|clock| *clock != FrameClock::default())
+            .count();
+
+        assert_eq!(clocks, 5);
+        assert!(!frame_counter.irq());
+    }
+}
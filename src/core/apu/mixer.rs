@@ -0,0 +1,221 @@
+use super::{blip::BlipBuffer, filter::FilterChain};
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+lazy_static! {
+    /// `95.52 / (8128 / n + 100)` for the sum of both pulse outputs
+    static ref PULSE_TABLE: [f32; 31] = {
+        let mut table = [0.0; 31];
+        for (n, value) in table.iter_mut().enumerate().skip(1) {
+            *value = 95.52 / (8128.0 / n as f32 + 100.0);
+        }
+        table
+    };
+
+    /// `163.67 / (24329 / n + 100)` for `3 * triangle + 2 * noise + dmc`
+    static ref TND_TABLE: [f32; 203] = {
+        let mut table = [0.0; 203];
+        for (n, value) in table.iter_mut().enumerate().skip(1) {
+            *value = 163.67 / (24329.0 / n as f32 + 100.0);
+        }
+        table
+    };
+}
+
+/// Combines the channel outputs the way the 2A03's resistor network does, the
+/// result ranges from 0.0 to roughly 1.0
+pub fn mix(pulse_1: u8, pulse_2: u8, triangle: u8, noise: u8, dmc: u8) -> f32 {
+    let pulse = PULSE_TABLE[(pulse_1 + pulse_2) as usize];
+    let tnd = TND_TABLE[3 * triangle as usize + 2 * noise as usize + dmc as usize];
+
+    pulse + tnd
+}
+
+/// Sound sources that can be recorded as separate stems
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+    /// Cartridge expansion audio, silent until a mapper provides some
+    Expansion,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 6] = [
+        Self::Pulse1,
+        Self::Pulse2,
+        Self::Triangle,
+        Self::Noise,
+        Self::Dmc,
+        Self::Expansion,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pulse1 => "pulse1",
+            Self::Pulse2 => "pulse2",
+            Self::Triangle => "triangle",
+            Self::Noise => "noise",
+            Self::Dmc => "dmc",
+            Self::Expansion => "expansion",
+        }
+    }
+
+    /// The channel's share of the mixer output when played on its own
+    fn level(&self, outputs: &[u8; 5]) -> f32 {
+        match self {
+            Self::Pulse1 => PULSE_TABLE[outputs[0] as usize],
+            Self::Pulse2 => PULSE_TABLE[outputs[1] as usize],
+            Self::Triangle => TND_TABLE[3 * outputs[2] as usize],
+            Self::Noise => TND_TABLE[2 * outputs[3] as usize],
+            Self::Dmc => TND_TABLE[outputs[4] as usize],
+            Self::Expansion => 0.0,
+        }
+    }
+}
+
+/// Band-limits, resamples and filters a single signal
+#[derive(Debug, Clone)]
+struct Stream {
+    blip: BlipBuffer,
+    filters: FilterChain,
+    level: f32,
+    samples: Vec<f32>,
+}
+
+impl Stream {
+    fn new(clock_rate: f64, sample_rate: u32) -> Self {
+        Self {
+            blip: BlipBuffer::new(clock_rate, sample_rate as f64),
+            filters: FilterChain::new(sample_rate as f32),
+            level: 0.0,
+            samples: Vec::new(),
+        }
+    }
+
+    fn set_rates(&mut self, clock_rate: f64, sample_rate: u32) {
+        self.blip.set_rates(clock_rate, sample_rate as f64);
+        self.filters.set_sample_rate(sample_rate as f32);
+    }
+
+    fn clock(&mut self, clock: usize, level: f32) {
+        if level != self.level {
+            self.blip.add_delta(clock, level - self.level);
+            self.level = level;
+        }
+    }
+
+    fn end_frame(&mut self, clocks: usize) {
+        self.blip.end_frame(clocks);
+
+        let start = self.samples.len();
+        self.blip.read_samples(&mut self.samples);
+        for sample in &mut self.samples[start..] {
+            *sample = self.filters.process(*sample);
+        }
+    }
+}
+
+/// Turns the channel outputs into filtered PCM at the host sample rate
+#[derive(Debug, Clone)]
+pub struct Mixer {
+    clock_rate: f64,
+    sample_rate: u32,
+    output: Stream,
+    /// One stream per entry of `Channel::ALL` while stems are being recorded
+    stems: Option<Vec<Stream>>,
+    frame_clock: usize,
+}
+
+impl Mixer {
+    pub fn new(clock_rate: f64, sample_rate: u32) -> Self {
+        Self {
+            clock_rate,
+            sample_rate,
+            output: Stream::new(clock_rate, sample_rate),
+            stems: None,
+            frame_clock: 0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_rates(&mut self, clock_rate: f64, sample_rate: u32) {
+        self.clock_rate = clock_rate;
+        self.sample_rate = sample_rate;
+        self.output.set_rates(clock_rate, sample_rate);
+        self.stems
+            .iter_mut()
+            .flatten()
+            .for_each(|stem| stem.set_rates(clock_rate, sample_rate));
+    }
+
+    pub fn set_stems_enabled(&mut self, enabled: bool) {
+        self.stems = match enabled {
+            true => Some(
+                Channel::ALL
+                    .iter()
+                    .map(|_| Stream::new(self.clock_rate, self.sample_rate))
+                    .collect(),
+            ),
+            false => None,
+        };
+    }
+
+    /// Feeds the outputs of pulse 1, pulse 2, triangle, noise and DMC for one APU clock
+    pub fn clock(&mut self, outputs: [u8; 5]) {
+        let [pulse_1, pulse_2, triangle, noise, dmc] = outputs;
+        let level = mix(pulse_1, pulse_2, triangle, noise, dmc);
+        self.output.clock(self.frame_clock, level);
+
+        if let Some(stems) = &mut self.stems {
+            for (stem, channel) in stems.iter_mut().zip(Channel::ALL) {
+                stem.clock(self.frame_clock, channel.level(&outputs));
+            }
+        }
+
+        self.frame_clock += 1;
+    }
+
+    /// Resamples and filters everything clocked since the previous frame
+    pub fn end_frame(&mut self) {
+        self.output.end_frame(self.frame_clock);
+        self.stems
+            .iter_mut()
+            .flatten()
+            .for_each(|stem| stem.end_frame(self.frame_clock));
+
+        self.frame_clock = 0;
+    }
+
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.output.samples)
+    }
+
+    /// Takes the samples of a single channel, empty unless stems are enabled
+    pub fn take_stem_samples(&mut self, channel: Channel) -> Vec<f32> {
+        let index = Channel::ALL.iter().position(|&c| c == channel).unwrap_or(0);
+
+        self.stems
+            .as_mut()
+            .map(|stems| std::mem::take(&mut stems[index].samples))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mix;
+
+    #[test]
+    fn mixer_output_follows_the_lookup_tables() {
+        assert_eq!(mix(0, 0, 0, 0, 0), 0.0);
+        assert!((mix(15, 15, 0, 0, 0) - 0.2575).abs() < 0.0001);
+        assert!((mix(15, 15, 15, 15, 127) - 1.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,366 @@
+mod blip;
+mod dmc;
+mod envelope;
+mod filter;
+mod frame_counter;
+mod length_counter;
+mod mixer;
+mod noise;
+mod pulse;
+mod sweep;
+mod triangle;
+
+use super::{Region, SubComponent};
+use crate::{
+    error::Result,
+    state::{StateReader, StateWriter, Stateful},
+};
+use envelope::Envelope;
+use frame_counter::{FrameClock, FrameCounter};
+use length_counter::LengthCounter;
+use mixer::Mixer;
+use sweep::Sweep;
+
+pub use dmc::Dmc;
+pub use mixer::{mix, Channel, DEFAULT_SAMPLE_RATE};
+pub use noise::Noise;
+pub use pulse::Pulse;
+pub use triangle::Triangle;
+
+const STATUS: u16 = 0x4015;
+const FRAME_COUNTER: u16 = 0x4017;
+
+/// The 2A03's audio processing unit, clocked by the CPU
+#[derive(Debug, Clone)]
+pub struct Apu {
+    region: Region,
+    pulse_1: Pulse,
+    pulse_2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_counter: FrameCounter,
+    mixer: Mixer,
+    cycles: SubComponent<usize>,
+    /// Cycle on which the DMC last asked for a sample byte
+    dmc_requested_at: Option<usize>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new(Region::default())
+    }
+}
+
+impl Apu {
+    pub fn new(region: Region) -> Self {
+        Self {
+            region,
+            pulse_1: Pulse::new(true),
+            pulse_2: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::new(region),
+            dmc: Dmc::new(region),
+            frame_counter: FrameCounter::new(region),
+            mixer: Mixer::new(region.cpu_clock_rate(), DEFAULT_SAMPLE_RATE),
+            cycles: SubComponent::default(),
+            dmc_requested_at: None,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.noise.set_region(region);
+        self.dmc.set_region(region);
+        self.frame_counter.set_region(region);
+        self.mixer
+            .set_rates(region.cpu_clock_rate(), self.mixer.sample_rate());
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.mixer.sample_rate()
+    }
+
+    /// Sets the host rate, in Hz, samples are produced at
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.mixer
+            .set_rates(self.region.cpu_clock_rate(), sample_rate);
+    }
+
+    /// Current output of the non-linear mixer, before filtering and resampling
+    pub fn output(&self) -> f32 {
+        mix(
+            self.pulse_1.output(),
+            self.pulse_2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        )
+    }
+
+    /// Resamples the audio clocked since the previous call, normally once per frame
+    pub fn end_frame(&mut self) {
+        self.mixer.end_frame();
+    }
+
+    /// Takes the samples produced so far, mono and roughly in `-1.0..=1.0`
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        self.mixer.take_samples()
+    }
+
+    /// Takes the samples produced so far as signed 16-bit PCM
+    pub fn take_samples_i16(&mut self) -> Vec<i16> {
+        to_i16(self.take_samples())
+    }
+
+    /// Produces a separate sample stream per channel alongside the mixed output
+    pub fn set_stems_enabled(&mut self, enabled: bool) {
+        self.mixer.set_stems_enabled(enabled);
+    }
+
+    /// Takes the samples of a single channel, empty unless stems are enabled
+    pub fn take_stem_samples(&mut self, channel: Channel) -> Vec<f32> {
+        self.mixer.take_stem_samples(channel)
+    }
+
+    pub fn pulse_1(&self) -> &Pulse {
+        &self.pulse_1
+    }
+
+    pub fn pulse_2(&self) -> &Pulse {
+        &self.pulse_2
+    }
+
+    pub fn triangle(&self) -> &Triangle {
+        &self.triangle
+    }
+
+    pub fn noise(&self) -> &Noise {
+        &self.noise
+    }
+
+    pub fn dmc(&self) -> &Dmc {
+        &self.dmc
+    }
+
+    /// Level of the APU's IRQ line
+    pub fn irq_pending(&self) -> bool {
+        self.frame_counter.irq() || self.dmc.irq()
+    }
+
+    /// Address the DMC wants fetched into its sample buffer
+    pub fn dmc_dma_request(&self) -> Option<u16> {
+        self.dmc.dma_request()
+    }
+
+    pub fn complete_dmc_dma(&mut self, byte: u8) {
+        self.dmc.fill_sample_buffer(byte);
+    }
+
+    /// Whether the DMC raised its fetch request on the most recently ticked cycle
+    pub fn dmc_requested_on_last_cycle(&self) -> bool {
+        self.dmc_requested_at == Some(self.cycles.get())
+    }
+
+    /// Handles writes to `$4000-$4013`, `$4015` and `$4017`
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse_1.write_register(addr - 0x4000, value),
+            0x4004..=0x4007 => self.pulse_2.write_register(addr - 0x4004, value),
+            0x4008..=0x400B => self.triangle.write_register(addr - 0x4008, value),
+            0x400C..=0x400F => self.noise.write_register(addr - 0x400C, value),
+            0x4010..=0x4013 => self.dmc.write_register(addr - 0x4010, value),
+            STATUS => {
+                self.pulse_1.set_enabled(value & 0b0001 != 0);
+                self.pulse_2.set_enabled(value & 0b0010 != 0);
+                self.triangle.set_enabled(value & 0b0100 != 0);
+                self.noise.set_enabled(value & 0b1000 != 0);
+                self.dmc.set_enabled(value & 0b1_0000 != 0);
+            }
+            FRAME_COUNTER => self.frame_counter.write(value, self.cycles.get()),
+            _ => {}
+        }
+    }
+
+    /// Reads `$4015`, reporting which channels still have length remaining and
+    /// whether the frame IRQ fired, acknowledging it
+    pub fn read_status(&mut self) -> u8 {
+        let status = (self.pulse_1.is_active() as u8)
+            | (self.pulse_2.is_active() as u8) << 1
+            | (self.triangle.is_active() as u8) << 2
+            | (self.noise.is_active() as u8) << 3
+            | (self.dmc.is_active() as u8) << 4
+            | (self.frame_counter.irq() as u8) << 6
+            | (self.dmc.irq() as u8) << 7;
+
+        self.frame_counter.acknowledge_irq();
+
+        status
+    }
+
+    pub fn tick(&mut self, cycles: usize) {
+        (0..cycles).for_each(|_| self.clock());
+    }
+
+    fn clock(&mut self) {
+        let dmc_pending = self.dmc.dma_request().is_some();
+        let FrameClock {
+            quarter_frame,
+            half_frame,
+        } = self.frame_counter.clock();
+
+        if quarter_frame {
+            self.pulse_1.clock_quarter_frame();
+            self.pulse_2.clock_quarter_frame();
+            self.triangle.clock_quarter_frame();
+            self.noise.clock_quarter_frame();
+        }
+
+        if half_frame {
+            self.pulse_1.clock_half_frame();
+            self.pulse_2.clock_half_frame();
+            self.triangle.clock_half_frame();
+            self.noise.clock_half_frame();
+        }
+
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+
+        // pulse timers run at half the CPU clock
+        if self.cycles.get() % 2 == 1 {
+            self.pulse_1.clock_timer();
+            self.pulse_2.clock_timer();
+        }
+
+        self.mixer.clock([
+            self.pulse_1.output(),
+            self.pulse_2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        ]);
+        self.cycles.increment();
+
+        if !dmc_pending && self.dmc.dma_request().is_some() {
+            self.dmc_requested_at = Some(self.cycles.get());
+        }
+    }
+}
+
+/// Converts samples in `-1.0..=1.0` to signed 16-bit PCM
+pub fn to_i16(samples: Vec<f32>) -> Vec<i16> {
+    samples
+        .into_iter()
+        .map(|sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+        .collect()
+}
+
+impl Stateful for Apu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.region.save_state(writer);
+        self.pulse_1.save_state(writer);
+        self.pulse_2.save_state(writer);
+        self.triangle.save_state(writer);
+        self.noise.save_state(writer);
+        self.dmc.save_state(writer);
+        self.frame_counter.save_state(writer);
+        self.cycles.save_state(writer);
+        self.dmc_requested_at.save_state(writer);
+    }
+
+    /// The mixer isn't part of the state, audio simply carries on from where the
+    /// host left it
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.region.load_state(reader)?;
+        self.pulse_1.load_state(reader)?;
+        self.pulse_2.load_state(reader)?;
+        self.triangle.load_state(reader)?;
+        self.noise.load_state(reader)?;
+        self.dmc.load_state(reader)?;
+        self.frame_counter.load_state(reader)?;
+        self.cycles.load_state(reader)?;
+        self.dmc_requested_at.load_state(reader)?;
+        self.set_region(self.region);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Apu, Channel};
+
+    #[test]
+    fn length_counters_are_reported_and_cleared_through_status() {
+        let mut apu = Apu::default();
+        apu.write_register(0x4015, 0b11);
+        apu.write_register(0x4003, 0b0000_1000);
+
+        assert_eq!(apu.read_status(), 0b01);
+
+        apu.write_register(0x4015, 0b10);
+        assert_eq!(apu.read_status(), 0b00);
+    }
+
+    #[test]
+    fn length_counter_runs_out_after_enough_half_frames() {
+        let mut apu = Apu::default();
+        apu.write_register(0x4015, 0b01);
+        // length index 3 loads a count of 2
+        apu.write_register(0x4003, 3 << 3);
+
+        apu.tick(14913);
+        assert_eq!(apu.read_status(), 0b01);
+
+        apu.tick(29829 - 14913);
+        assert_eq!(apu.read_status() & 0b1111, 0b00);
+    }
+
+    #[test]
+    fn reading_status_acknowledges_the_frame_irq() {
+        let mut apu = Apu::default();
+        apu.tick(30000);
+
+        assert!(apu.irq_pending());
+        assert_eq!(apu.read_status() & 0b0100_0000, 0b0100_0000);
+        assert!(!apu.irq_pending());
+    }
+
+    #[test]
+    fn a_frame_of_audio_matches_the_host_rate() {
+        let mut apu = Apu::default();
+        apu.set_sample_rate(48_000);
+        apu.write_register(0x4015, 0b01);
+        apu.write_register(0x4000, 0b1011_1111);
+        apu.write_register(0x4002, 0xFD);
+        apu.write_register(0x4003, 0b0000_1000);
+
+        apu.tick(29_781);
+        apu.end_frame();
+        let samples = apu.take_samples();
+
+        assert_eq!(samples.len(), 798);
+        assert!(samples.iter().any(|sample| sample.abs() > 0.05));
+        assert!(apu.take_samples().is_empty());
+    }
+
+    #[test]
+    fn stems_isolate_their_channel() {
+        let mut apu = Apu::default();
+        apu.set_stems_enabled(true);
+        apu.write_register(0x4015, 0b01);
+        apu.write_register(0x4000, 0b1011_1111);
+        apu.write_register(0x4002, 0xFD);
+        apu.write_register(0x4003, 0b0000_1000);
+
+        apu.tick(29_781);
+        apu.end_frame();
+
+        let pulse_1 = apu.take_stem_samples(Channel::Pulse1);
+        let pulse_2 = apu.take_stem_samples(Channel::Pulse2);
+        assert_eq!(pulse_1.len(), pulse_2.len());
+        assert!(pulse_1.iter().any(|sample| sample.abs() > 0.05));
+        assert!(pulse_2.iter().all(|&sample| sample == 0.0));
+    }
+}
@@ -0,0 +1,103 @@
+use super::{Envelope, LengthCounter};
+use crate::{core::Region, state::stateful};
+
+/// Pseudo-random noise channel, `$400C-$400F`
+#[derive(Debug, Clone)]
+pub struct Noise {
+    periods: &'static [u16; 16],
+    timer_period: u16,
+    timer: u16,
+    short_mode: bool,
+    /// 15-bit linear feedback shift register
+    shift_register: u16,
+    envelope: Envelope,
+    length_counter: LengthCounter,
+}
+
+impl Noise {
+    pub fn new(region: Region) -> Self {
+        let periods = region.noise_periods();
+
+        Self {
+            periods,
+            timer_period: periods[0],
+            timer: 0,
+            short_mode: false,
+            shift_register: 1,
+            envelope: Envelope::default(),
+            length_counter: LengthCounter::default(),
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.periods = region.noise_periods();
+    }
+
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register & 0b11 {
+            0 => {
+                self.length_counter.set_halted(value & 0b0010_0000 != 0);
+                self.envelope.write(value);
+            }
+            1 => {}
+            2 => {
+                self.short_mode = value & 0b1000_0000 != 0;
+                self.timer_period = self.periods[(value & 0b1111) as usize];
+            }
+            _ => {
+                self.length_counter.load(value >> 3);
+                self.envelope.restart();
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    /// Clocked every CPU cycle, the period table is expressed in CPU cycles
+    pub fn clock_timer(&mut self) {
+        match self.timer {
+            0 => {
+                self.timer = self.timer_period - 1;
+
+                let tap = match self.short_mode {
+                    true => 6,
+                    false => 1,
+                };
+                let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+                self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+            }
+            _ => self.timer -= 1,
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    pub fn output(&self) -> u8 {
+        match self.shift_register & 1 == 0 && self.is_active() {
+            true => self.envelope.output(),
+            false => 0,
+        }
+    }
+}
+
+// the period table follows the region and is restored with it
+stateful!(Noise {
+    timer_period,
+    timer,
+    short_mode,
+    shift_register,
+    envelope,
+    length_counter,
+});
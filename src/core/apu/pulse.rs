@@ -0,0 +1,101 @@
+use super::{Envelope, LengthCounter, Sweep};
+use crate::state::stateful;
+
+const DUTY_SEQUENCES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/// Square wave channel, `$4000-$4003` for pulse 1 and `$4004-$4007` for pulse 2
+#[derive(Debug, Clone)]
+pub struct Pulse {
+    duty: u8,
+    step: u8,
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    sweep: Sweep,
+    length_counter: LengthCounter,
+}
+
+impl Pulse {
+    pub fn new(ones_complement_sweep: bool) -> Self {
+        Self {
+            duty: 0,
+            step: 0,
+            timer_period: 0,
+            timer: 0,
+            envelope: Envelope::default(),
+            sweep: Sweep::new(ones_complement_sweep),
+            length_counter: LengthCounter::default(),
+        }
+    }
+
+    /// Writes one of the channel's four registers, `register` being the offset from its base
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register & 0b11 {
+            0 => {
+                self.duty = value >> 6;
+                self.length_counter.set_halted(value & 0b0010_0000 != 0);
+                self.envelope.write(value);
+            }
+            1 => self.sweep.write(value),
+            2 => self.timer_period = (self.timer_period & 0x700) | value as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0xFF) | ((value as u16 & 0b111) << 8);
+                self.length_counter.load(value >> 3);
+                self.step = 0;
+                self.envelope.restart();
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    /// Clocked every APU cycle, i.e. every other CPU cycle
+    pub fn clock_timer(&mut self) {
+        match self.timer {
+            0 => {
+                self.timer = self.timer_period;
+                self.step = (self.step + 1) % 8;
+            }
+            _ => self.timer -= 1,
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+        self.timer_period = self.sweep.clock(self.timer_period);
+    }
+
+    pub fn output(&self) -> u8 {
+        let high = DUTY_SEQUENCES[self.duty as usize][self.step as usize] != 0;
+
+        match high && self.is_active() && !self.sweep.is_muting(self.timer_period) {
+            true => self.envelope.output(),
+            false => 0,
+        }
+    }
+}
+
+stateful!(Pulse {
+    duty,
+    step,
+    timer_period,
+    timer,
+    envelope,
+    sweep,
+    length_counter,
+});
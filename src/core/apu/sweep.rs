@@ -0,0 +1,106 @@
+use crate::state::stateful;
+
+/// Periodically bends a pulse channel's period up or down
+#[derive(Debug, Clone)]
+pub struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    reload: bool,
+    divider: u8,
+    /// Pulse 1 negates with ones' complement, subtracting one more than pulse 2
+    ones_complement: bool,
+}
+
+impl Sweep {
+    pub fn new(ones_complement: bool) -> Self {
+        Self {
+            enabled: false,
+            period: 0,
+            negate: false,
+            shift: 0,
+            reload: false,
+            divider: 0,
+            ones_complement,
+        }
+    }
+
+    /// Handles the `EPPP NSSS` bits of `$4001` and `$4005`
+    pub fn write(&mut self, value: u8) {
+        self.enabled = value & 0b1000_0000 != 0;
+        self.period = (value >> 4) & 0b111;
+        self.negate = value & 0b1000 != 0;
+        self.shift = value & 0b111;
+        self.reload = true;
+    }
+
+    pub fn target_period(&self, timer_period: u16) -> u16 {
+        let change = timer_period >> self.shift;
+
+        match (self.negate, self.ones_complement) {
+            (true, true) => timer_period.saturating_sub(change + 1),
+            (true, false) => timer_period.saturating_sub(change),
+            (false, _) => timer_period + change,
+        }
+    }
+
+    /// The channel is silenced while its period is too low or the target overflows,
+    /// even when the sweep unit is disabled
+    pub fn is_muting(&self, timer_period: u16) -> bool {
+        timer_period < 8 || self.target_period(timer_period) > 0x7FF
+    }
+
+    /// Clocked by the frame counter on half frames, returns the adjusted period
+    pub fn clock(&mut self, timer_period: u16) -> u16 {
+        let mut period = timer_period;
+        if self.divider == 0 && self.enabled && self.shift != 0 && !self.is_muting(timer_period) {
+            period = self.target_period(timer_period);
+        }
+
+        match self.divider == 0 || self.reload {
+            true => {
+                self.divider = self.period;
+                self.reload = false;
+            }
+            false => self.divider -= 1,
+        }
+
+        period
+    }
+}
+
+stateful!(Sweep {
+    enabled,
+    period,
+    negate,
+    shift,
+    reload,
+    divider,
+    ones_complement,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::Sweep;
+
+    #[test]
+    fn pulse_one_negates_with_ones_complement() {
+        let (mut pulse_1, mut pulse_2) = (Sweep::new(true), Sweep::new(false));
+        pulse_1.write(0b1000_1001);
+        pulse_2.write(0b1000_1001);
+
+        assert_eq!(pulse_1.target_period(0x100), 0x100 - 0x80 - 1);
+        assert_eq!(pulse_2.target_period(0x100), 0x100 - 0x80);
+    }
+
+    #[test]
+    fn overflowing_target_mutes_even_when_disabled() {
+        let mut sweep = Sweep::new(false);
+        sweep.write(0b0000_0001);
+
+        assert!(sweep.is_muting(0x600));
+        assert!(sweep.is_muting(0x007));
+        assert!(!sweep.is_muting(0x100));
+    }
+}
@@ -0,0 +1,95 @@
+use super::LengthCounter;
+use crate::state::stateful;
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, //
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, //
+];
+
+/// Triangle wave channel, `$4008-$400B`
+#[derive(Debug, Default, Clone)]
+pub struct Triangle {
+    step: u8,
+    timer_period: u16,
+    timer: u16,
+    control: bool,
+    linear_reload: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    length_counter: LengthCounter,
+}
+
+impl Triangle {
+    pub fn write_register(&mut self, register: u16, value: u8) {
+        match register & 0b11 {
+            0 => {
+                self.control = value & 0b1000_0000 != 0;
+                self.length_counter.set_halted(self.control);
+                self.linear_reload_value = value & 0b0111_1111;
+            }
+            1 => {}
+            2 => self.timer_period = (self.timer_period & 0x700) | value as u16,
+            _ => {
+                self.timer_period = (self.timer_period & 0xFF) | ((value as u16 & 0b111) << 8);
+                self.length_counter.load(value >> 3);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.length_counter.set_enabled(enabled);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.length_counter.is_active()
+    }
+
+    /// Clocked every CPU cycle
+    pub fn clock_timer(&mut self) {
+        match self.timer {
+            0 => {
+                self.timer = self.timer_period;
+
+                // periods below 2 produce an ultrasonic tone, real hardware averages it
+                // out so the sequencer is held instead of popping between extremes
+                if self.is_active() && self.linear_counter > 0 && self.timer_period >= 2 {
+                    self.step = (self.step + 1) % 32;
+                }
+            }
+            _ => self.timer -= 1,
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        match self.linear_reload {
+            true => self.linear_counter = self.linear_reload_value,
+            false => self.linear_counter = self.linear_counter.saturating_sub(1),
+        }
+
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    /// Silencing the triangle halts its sequencer rather than muting it, so it keeps
+    /// outputting the step it stopped on
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.step as usize]
+    }
+}
+
+stateful!(Triangle {
+    step,
+    timer_period,
+    timer,
+    control,
+    linear_reload,
+    linear_reload_value,
+    linear_counter,
+    length_counter,
+});
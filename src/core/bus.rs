@@ -1,27 +1,57 @@
 use std::mem;
 
-use super::{Cartridge, Ppu, Ram, Rom, SubComponent};
+use super::{
+    dma::OAM_DMA_CYCLES,
+    input::{DeviceSetup, InputDevice, InputState, EXPANSION_PORT, PORTS},
+    Apu, BuiltinPalette, Cartridge, Dma, Palette, Ppu, Ram, Region, Rom, SubComponent,
+};
 use crate::{
     error::{Error, Result},
     io::{Read, Write},
+    kb,
+    state::{StateReader, StateWriter, Stateful},
 };
 
 const RAM_START: u16 = 0x0000;
 const RAM_MIRRORS_END: u16 = 0x1FFF;
 const PPU_REGISTERS_START: u16 = 0x2000;
 const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+const PPU_DATA: u16 = 0x2007;
+const APU_REGISTERS_START: u16 = 0x4000;
+const APU_REGISTERS_END: u16 = 0x4013;
+const OAM_DMA: u16 = 0x4014;
+const APU_STATUS: u16 = 0x4015;
+const JOYPAD_1: u16 = 0x4016;
+/// Reads come from the second controller port, writes go to the APU frame counter
+const JOYPAD_2: u16 = 0x4017;
+const APU_FRAME_COUNTER: u16 = 0x4017;
+/// Bits of a controller read left over from the last value on the data bus
+const JOYPAD_OPEN_BUS_MASK: u8 = 0b1110_0000;
+const PRG_RAM_START: u16 = 0x6000;
+const PRG_RAM_END: u16 = 0x7FFF;
+pub const PRG_RAM_SIZE: usize = kb!(8);
 
 #[allow(unused)]
 #[derive(Debug)]
 pub struct Bus {
     program_rom: Rom,
     ram: Ram,
+    /// Cartridge work RAM at `$6000-$7FFF`
+    prg_ram: Vec<u8>,
     ppu: Ppu,
-    apu: (),
-    keypad: (),
-    dma: (),
+    apu: Apu,
+    ports: [Box<dyn InputDevice>; PORTS],
+    expansion: Option<Box<dyn InputDevice>>,
+    /// Colors the picture is drawn with, handed to every device that gets connected
+    palette: Palette,
+    dma: Dma,
     mmc: (),
+    open_bus: u8,
+    last_read: u16,
+    region: Region,
     cycles: SubComponent<usize>,
+    /// PPU dots owed to the PPU when the clock ratio isn't a whole number
+    ppu_remainder: usize,
 }
 
 impl Bus {
@@ -29,17 +59,27 @@ impl Bus {
         let program_rom = cartridge.program_rom().to_owned();
         let character_rom = cartridge.character_rom().to_owned();
         let mirroring = cartridge.screen_mirroring().to_owned();
-        let ppu = Ppu::new(character_rom, mirroring);
+        let region = cartridge.region();
+        let mut ppu = Ppu::new(character_rom, mirroring);
+        ppu.set_region(region);
+        let (ports, expansion) = cartridge.input_devices().devices();
 
         Self {
             program_rom,
             ram: Ram::default(),
+            prg_ram: vec![0; PRG_RAM_SIZE],
             ppu,
-            apu: (),
-            keypad: (),
-            dma: (),
+            apu: Apu::new(region),
+            ports,
+            expansion,
+            palette: Palette::from(BuiltinPalette::default()),
+            dma: Dma::default(),
             mmc: (),
+            open_bus: 0,
+            last_read: 0,
+            region,
             cycles: SubComponent::default(),
+            ppu_remainder: 0,
         }
     }
 
@@ -55,32 +95,205 @@ impl Bus {
             .try_for_each(|(i, byte)| self.write_byte(offset + i as u16, byte))
     }
 
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Overrides the timing picked from the cartridge header
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu.set_region(region);
+        self.apu.set_region(region);
+    }
+
     pub fn tick(&mut self, cycles: usize) {
         self.cycles.wrapping_add(cycles);
-        self.ppu.tick(cycles * 3);
+        self.apu.tick(cycles);
+
+        let (numerator, denominator) = self.region.ppu_dots_per_cpu_cycle();
+        self.ppu_remainder += cycles * numerator;
+        let frame_complete = self.ppu.tick(self.ppu_remainder / denominator);
+        self.ppu_remainder %= denominator;
+
+        if frame_complete {
+            self.apu.end_frame();
+        }
+    }
+
+    /// Carries out pending DMA transfers, stalling the CPU while they hold the bus.
+    /// DMC sample fetches take priority and may interleave with an OAM transfer
+    pub fn run_dma(&mut self) -> Result<()> {
+        self.run_dmc_dma(false)?;
+
+        if let Some(page) = self.dma.take_oam_request() {
+            let stall = Dma::oam_stall_cycles(self.cycles.get());
+            self.dma.record_stall(stall);
+
+            // halt cycle, plus an alignment cycle when starting on an odd cycle
+            self.tick(stall + 1 - OAM_DMA_CYCLES);
+
+            let base = (page as u16) << 8;
+            let mut data = [0; 256];
+            for (offset, byte) in data.iter_mut().enumerate() {
+                *byte = self.read_byte(base + offset as u16)?;
+                self.tick(2);
+                self.run_dmc_dma(true)?;
+            }
+
+            self.ppu.write_oam_dma(&data);
+        }
+
+        Ok(())
+    }
+
+    fn run_dmc_dma(&mut self, during_oam: bool) -> Result<()> {
+        if let Some(addr) = self.apu.dmc_dma_request() {
+            let last_read = self.last_read;
+
+            // the halted CPU repeats the read it was stuck on, registers with read side
+            // effects see it twice
+            if !during_oam && self.apu.dmc_requested_on_last_cycle() {
+                self.repeat_read(last_read)?;
+            }
+
+            let byte = self.read_byte(addr)?;
+            self.apu.complete_dmc_dma(byte);
+            self.last_read = last_read;
+
+            let stall = Dma::dmc_stall_cycles(during_oam);
+            self.dma.record_stall(stall);
+            self.tick(stall);
+        }
+
+        Ok(())
+    }
+
+    fn repeat_read(&mut self, addr: u16) -> Result<()> {
+        if let PPU_DATA | JOYPAD_1 | JOYPAD_2 = addr {
+            self.read_byte(addr)?;
+        }
+
+        Ok(())
+    }
+
+    /// The 2K of work RAM at `$0000-$07FF`
+    pub fn ram(&self) -> &Ram {
+        &self.ram
+    }
+
+    /// The cartridge's 8K of work RAM at `$6000-$7FFF`
+    pub fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.ppu
+    }
+
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    /// Swaps every connected device for the ones of the given setup
+    pub fn connect_devices(&mut self, setup: DeviceSetup) {
+        (self.ports, self.expansion) = setup.devices();
+        self.set_palette(&self.palette.clone());
+    }
+
+    /// Plugs a device into a controller port, replacing whatever was there
+    pub fn connect(&mut self, port: usize, mut device: Box<dyn InputDevice>) {
+        device.set_palette(&self.palette);
+        self.ports[port] = device;
+    }
+
+    pub fn device(&self, port: usize) -> &dyn InputDevice {
+        self.ports[port].as_ref()
+    }
+
+    /// Plugs a device into the Famicom expansion port, `None` leaves it empty
+    pub fn connect_expansion(&mut self, mut device: Option<Box<dyn InputDevice>>) {
+        if let Some(device) = &mut device {
+            device.set_palette(&self.palette);
+        }
+        self.expansion = device;
+    }
+
+    pub fn expansion_device(&self) -> Option<&dyn InputDevice> {
+        self.expansion.as_deref()
+    }
+
+    /// Sets the colors the picture is drawn with, light guns judge brightness by them
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.palette = palette.clone();
+
+        for device in self.ports.iter_mut().chain(self.expansion.as_mut()) {
+            device.set_palette(palette);
+        }
+    }
+
+    /// Hands the host's controller state to the devices plugged into the ports
+    pub fn set_input(&mut self, input: &InputState) {
+        for (port, device) in self.ports.iter_mut().enumerate() {
+            device.set_input(input, port);
+        }
+
+        if let Some(device) = &mut self.expansion {
+            device.set_input(input, EXPANSION_PORT);
+        }
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles.get()
     }
 
     pub fn poll_nmi_status(&mut self) -> Option<u8> {
         self.ppu.poll_nmi_interrupt()
     }
+
+    /// Level of the shared IRQ line, it stays asserted until the source is acknowledged
+    pub fn poll_irq_status(&self) -> bool {
+        self.apu.irq_pending()
+    }
 }
 
 // UNWRAP: we've ensured that a rom is loaded
 impl Read for Bus {
     fn read_byte(&mut self, addr: u16) -> Result<u8> {
-        match addr {
+        self.last_read = addr;
+
+        let byte = match addr {
             RAM_START..=RAM_MIRRORS_END => self.ram.read_byte(addr & 0x07FF),
-            0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 | 0x4014 => Err(Error::Illegal(format!(
-                "Attempted to read from write-only PPU address: {addr:#x}"
-            ))),
+            // write-only, reads return the PPU's I/O latch
+            0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 => Ok(self.ppu.read_open_bus()),
             0x2002 => Ok(self.ppu.read_status()),
             0x2004 => Ok(self.ppu.read_oam_data()),
-            0x2007 => self.ppu.read_data(),
+            PPU_DATA => self.ppu.read_data(),
             0x2008..=PPU_REGISTERS_MIRRORS_END => {
                 let mirror_down_addr = addr & 0b0010_0000_0000_0111;
 
                 self.read_byte(mirror_down_addr)
             }
+            // write-only, the CPU sees whatever was last left on the data bus
+            APU_REGISTERS_START..=APU_REGISTERS_END | OAM_DMA => Ok(self.open_bus),
+            APU_STATUS => Ok(self.apu.read_status()),
+            JOYPAD_1 | JOYPAD_2 => {
+                let port = (addr - JOYPAD_1) as usize;
+                let mut value = self.ports[port].read(port, &self.ppu);
+                if let Some(device) = &mut self.expansion {
+                    value |= device.read(port, &self.ppu);
+                }
+
+                Ok((self.open_bus & JOYPAD_OPEN_BUS_MASK) | (value & !JOYPAD_OPEN_BUS_MASK))
+            }
+            PRG_RAM_START..=PRG_RAM_END => Ok(self.prg_ram[(addr - PRG_RAM_START) as usize]),
             0x8000..=0xFFFF => {
                 let mut addr = addr - 0x8000;
                 if self.program_rom.len() == 0x4000 && addr >= 0x4000 {
@@ -92,12 +305,18 @@ impl Read for Bus {
             _ => Err(Error::Unsupported(format!(
                 "[READ] illegal address: {addr:#x}"
             ))),
-        }
+        }?;
+
+        self.open_bus = byte;
+
+        Ok(byte)
     }
 }
 
 impl Write for Bus {
     fn write_byte(&mut self, addr: u16, byte: u8) -> Result<()> {
+        self.open_bus = byte;
+
         match addr {
             RAM_START..=RAM_MIRRORS_END => {
                 let mirror_down_addr = addr & 0b11111111111;
@@ -112,9 +331,10 @@ impl Write for Bus {
                 self.ppu.write_to_mask(byte);
                 Ok(())
             }
-            0x2002 => Err(Error::Illegal(format!(
-                "attempted to write to PPU status register: {addr:#x}"
-            ))),
+            0x2002 => {
+                self.ppu.write_to_status(byte);
+                Ok(())
+            }
             0x2003 => {
                 self.ppu.write_to_oam_addr(byte);
                 Ok(())
@@ -137,6 +357,25 @@ impl Write for Bus {
 
                 self.write_byte(mirror_down_addr, byte)
             }
+            APU_REGISTERS_START..=APU_REGISTERS_END | APU_STATUS | APU_FRAME_COUNTER => {
+                self.apu.write_register(addr, byte);
+                Ok(())
+            }
+            OAM_DMA => {
+                self.dma.request_oam(byte);
+                Ok(())
+            }
+            JOYPAD_1 => {
+                self.ports
+                    .iter_mut()
+                    .chain(self.expansion.as_mut())
+                    .for_each(|device| device.write(byte));
+                Ok(())
+            }
+            PRG_RAM_START..=PRG_RAM_END => {
+                self.prg_ram[(addr - PRG_RAM_START) as usize] = byte;
+                Ok(())
+            }
             0x8000..=0xFFFF => Err(Error::Illegal(format!(
                 "attempted to write to Cartridge ROM: {addr:#x}"
             ))),
@@ -146,3 +385,188 @@ impl Write for Bus {
         }
     }
 }
+
+/// Saves the work RAM, bus latches and timing and the connected input devices, the
+/// PPU and APU are saved separately
+impl Stateful for Bus {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.ram.save_state(writer);
+        self.prg_ram.save_state(writer);
+        self.dma.save_state(writer);
+        self.open_bus.save_state(writer);
+        self.last_read.save_state(writer);
+        self.cycles.save_state(writer);
+        self.ppu_remainder.save_state(writer);
+
+        for device in self.ports.iter().map(Some).chain([self.expansion.as_ref()]) {
+            let name = device.map_or("", |device| device.name());
+            writer.write_u8(name.len() as u8);
+            writer.write_bytes(name.as_bytes());
+            if let Some(device) = device {
+                device.save_state(writer);
+            }
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.ram.load_state(reader)?;
+        self.prg_ram.load_state(reader)?;
+        if self.prg_ram.len() != PRG_RAM_SIZE {
+            return Err(Error::Illegal(format!(
+                "save state holds {} bytes of PRG-RAM",
+                self.prg_ram.len()
+            )));
+        }
+        self.dma.load_state(reader)?;
+        self.open_bus.load_state(reader)?;
+        self.last_read.load_state(reader)?;
+        self.cycles.load_state(reader)?;
+        self.ppu_remainder.load_state(reader)?;
+
+        let devices = self
+            .ports
+            .iter_mut()
+            .map(Some)
+            .chain([self.expansion.as_mut()]);
+        for (port, device) in devices.enumerate() {
+            let len = reader.read_u8()? as usize;
+            let saved = String::from_utf8_lossy(reader.read_bytes(len)?).into_owned();
+            let connected = device.as_ref().map_or("", |device| device.name());
+            if saved != connected {
+                return Err(Error::Illegal(format!(
+                    "save state expects {:?} in input port {port} but {:?} is connected",
+                    saved, connected
+                )));
+            }
+
+            if let Some(device) = device {
+                device.load_state(reader)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bus, Dma};
+    use crate::{
+        core::input::{Buttons, InputState},
+        error::Result,
+        io::{Read, Write},
+        test::nrom_cartridge,
+    };
+
+    fn bus() -> Bus {
+        Bus::new(&nrom_cartridge(&[]))
+    }
+
+    /// Copies `$0200-$02FF` into OAM through `$4014`, returning the cycles it took
+    fn oam_dma(bus: &mut Bus) -> Result<usize> {
+        for offset in 0..=0xFF {
+            bus.write_byte(0x0200 + offset, offset as u8 ^ 0xA5)?;
+        }
+
+        let start = bus.cycles();
+        bus.write_byte(0x4014, 0x02)?;
+        bus.run_dma()?;
+
+        Ok(bus.cycles() - start)
+    }
+
+    #[test]
+    fn oam_dma_copies_the_page_into_oam() -> Result<()> {
+        let mut bus = bus();
+        oam_dma(&mut bus)?;
+
+        for index in [0x00, 0x01, 0x7F, 0xFF] {
+            bus.write_byte(0x2003, index)?;
+            assert_eq!(bus.read_byte(0x2004)?, index ^ 0xA5);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn oam_dma_stalls_an_extra_cycle_when_started_on_an_odd_cycle() -> Result<()> {
+        let mut bus = bus();
+        assert_eq!(bus.cycles() % 2, 0);
+        assert_eq!(oam_dma(&mut bus)?, 513);
+
+        assert_eq!(bus.cycles() % 2, 1);
+        assert_eq!(oam_dma(&mut bus)?, 514);
+
+        Ok(())
+    }
+
+    /// Starts a 17 byte DMC sample at the fastest rate, which asks for its first
+    /// byte right away
+    fn start_dmc(bus: &mut Bus) -> Result<()> {
+        bus.write_byte(0x4010, 0x0F)?;
+        bus.write_byte(0x4012, 0x00)?;
+        bus.write_byte(0x4013, 0x01)?;
+        bus.write_byte(0x4015, 0x10)
+    }
+
+    /// Fills the DMC sample buffer, then runs until the DMC takes it and raises a
+    /// new fetch request on the last cycle ticked
+    fn run_until_dmc_request(bus: &mut Bus) -> Result<()> {
+        bus.run_dma()?;
+        while bus.apu().dmc_dma_request().is_none() {
+            bus.tick(1);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn dmc_fetch_on_a_joypad_read_clocks_the_joypad_twice() -> Result<()> {
+        let mut bus = bus();
+        let mut input = InputState::default();
+        input.press(0, Buttons::SELECT);
+        bus.set_input(&input);
+        start_dmc(&mut bus)?;
+        run_until_dmc_request(&mut bus)?;
+
+        bus.write_byte(0x4016, 1)?;
+        bus.write_byte(0x4016, 0)?;
+        assert_eq!(bus.read_byte(0x4016)? & 1, 0);
+        bus.run_dma()?;
+
+        // B was shifted out by the repeated read, Select is next
+        assert_eq!(bus.last_read, 0x4016);
+        assert_eq!(bus.read_byte(0x4016)? & 1, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn dmc_fetch_keeps_the_cpus_last_read() -> Result<()> {
+        let mut bus = bus();
+        start_dmc(&mut bus)?;
+        bus.read_byte(0x0123)?;
+
+        let start = bus.cycles();
+        bus.run_dma()?;
+        assert_eq!(bus.cycles() - start, 4);
+        assert_eq!(bus.last_read, 0x0123);
+        assert!(bus.apu().dmc_dma_request().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn dmc_fetch_during_oam_dma_adds_two_cycles() -> Result<()> {
+        let mut bus = bus();
+        start_dmc(&mut bus)?;
+        // the next request comes 432 cycles after this fetch, inside the OAM transfer
+        run_until_dmc_request(&mut bus)?;
+        bus.run_dma()?;
+
+        let oam_stall = Dma::oam_stall_cycles(bus.cycles());
+        assert_eq!(oam_dma(&mut bus)?, oam_stall + 2);
+
+        Ok(())
+    }
+}
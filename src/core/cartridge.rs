@@ -1,44 +1,55 @@
-use super::Rom;
+use super::{input::DeviceSetup, Region, Rom};
 use crate::{
     error::{Error, Result},
-    kb,
+    hash, kb,
+    state::{invalid, StateReader, StateWriter, Stateful},
 };
 use std::path::Path;
 
 const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
 const PROGRAM_ROM_PAGE_SIZE: usize = kb!(16);
 const CHARACTER_ROM_PAGE_SIZE: usize = kb!(8);
+const HEADER_SIZE: usize = 16;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Mirroring {
     Vertical,
     Horizontal,
+    SingleScreenLower,
+    SingleScreenUpper,
     FourScreen,
 }
 
+#[derive(Debug, Clone)]
 pub struct Cartridge {
     program_rom: Rom,
     character_rom: Rom,
-    mapper: u8,
+    mapper: u16,
     screen_mirroring: Mirroring,
+    region: Region,
+    input_devices: DeviceSetup,
 }
 
 impl Cartridge {
     pub fn new(data: Vec<u8>) -> Result<Self> {
-        if data[0..4] != NES_TAG {
+        if data.len() < HEADER_SIZE || data[0..4] != NES_TAG {
             return Err(Error::Unsupported(
                 "File is not in iNES file format".to_owned(),
             ));
         }
 
-        let mapper = (data[7] & 0b1111_0000) | (data[6] >> 4);
+        let mut mapper = ((data[7] & 0b1111_0000) | (data[6] >> 4)) as u16;
 
         let ines_ver = (data[7] >> 2) & 0b11;
-        if ines_ver != 0 {
-            return Err(Error::Unsupported(
-                "NES2.0 format is not supported".to_owned(),
-            ));
-        }
+        let nes2 = match ines_ver {
+            0 => false,
+            2 => true,
+            _ => {
+                return Err(Error::Unsupported(format!(
+                    "unrecognized iNES header version: {ines_ver}"
+                )))
+            }
+        };
 
         let four_screen = data[6] & 0b1000 != 0;
         let vertical_mirroring = data[6] & 0b1 != 0;
@@ -48,14 +59,46 @@ impl Cartridge {
             (true, _) => Mirroring::FourScreen,
         };
 
-        let program_rom_size = data[4] as usize * PROGRAM_ROM_PAGE_SIZE;
-        let character_rom_size = data[5] as usize * CHARACTER_ROM_PAGE_SIZE;
+        let (mut program_rom_pages, mut character_rom_pages) = (data[4] as usize, data[5] as usize);
+        let input_devices = match nes2 {
+            true => DeviceSetup::from_nes2_expansion_device(data[15]),
+            false => DeviceSetup::default(),
+        };
+
+        let region = match nes2 {
+            true => {
+                mapper |= ((data[8] & 0b1111) as u16) << 8;
+                if data[9] & 0x0F == 0x0F || data[9] & 0xF0 == 0xF0 {
+                    return Err(Error::Unsupported(
+                        "NES2.0 exponent-multiplier ROM sizes are not supported".to_owned(),
+                    ));
+                }
+
+                program_rom_pages |= ((data[9] & 0x0F) as usize) << 8;
+                character_rom_pages |= ((data[9] >> 4) as usize) << 8;
+
+                Region::from_nes2_timing(data[12])
+            }
+            false => match data[9] & 1 {
+                1 => Region::Pal,
+                _ => Region::Ntsc,
+            },
+        };
+
+        let program_rom_size = program_rom_pages * PROGRAM_ROM_PAGE_SIZE;
+        let character_rom_size = character_rom_pages * CHARACTER_ROM_PAGE_SIZE;
 
         let skip_trainer = data[6] & 0b100 != 0;
 
-        let program_rom_start = 16 + if skip_trainer { 512 } else { 0 };
+        let program_rom_start = HEADER_SIZE + if skip_trainer { 512 } else { 0 };
         let character_rom_start = program_rom_start + program_rom_size;
         let character_rom_end = character_rom_start + character_rom_size;
+        if data.len() < character_rom_end {
+            return Err(Error::Illegal(format!(
+                "ROM is truncated, expected {character_rom_end} bytes but got {}",
+                data.len()
+            )));
+        }
 
         let program_rom = Rom::new(data[program_rom_start..character_rom_start].to_vec());
         let character_rom = Rom::new(data[character_rom_start..character_rom_end].to_vec());
@@ -65,6 +108,8 @@ impl Cartridge {
             character_rom,
             mapper,
             screen_mirroring,
+            region,
+            input_devices,
         })
     }
 
@@ -76,13 +121,37 @@ impl Cartridge {
         &self.character_rom
     }
 
-    pub fn mapper(&self) -> u8 {
+    pub fn mapper(&self) -> u16 {
         self.mapper
     }
 
     pub fn screen_mirroring(&self) -> Mirroring {
         self.screen_mirroring
     }
+
+    /// Console timing the header asks for
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// CRC-32 of the PRG and CHR data, identifies the game regardless of its header
+    pub fn hash(&self) -> u32 {
+        hash::crc32(&self.data())
+    }
+
+    /// MD5 of the PRG and CHR data, the checksum FCEUX movies carry
+    pub fn md5(&self) -> [u8; 16] {
+        hash::md5(&self.data())
+    }
+
+    /// Input devices the header asks for
+    pub fn input_devices(&self) -> DeviceSetup {
+        self.input_devices
+    }
+
+    fn data(&self) -> Vec<u8> {
+        [self.program_rom.as_ref(), self.character_rom.as_ref()].concat()
+    }
 }
 
 impl TryFrom<&Path> for Cartridge {
@@ -94,3 +163,74 @@ impl TryFrom<&Path> for Cartridge {
         Self::new(data)
     }
 }
+
+impl Stateful for Mirroring {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(*self as u8);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        *self = match reader.read_u8()? {
+            0 => Self::Vertical,
+            1 => Self::Horizontal,
+            2 => Self::SingleScreenLower,
+            3 => Self::SingleScreenUpper,
+            4 => Self::FourScreen,
+            value => return Err(invalid("mirroring mode", value)),
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cartridge, PROGRAM_ROM_PAGE_SIZE};
+    use crate::core::{input::DeviceSetup, Region};
+
+    fn image(flags_7: u8, timing: u8) -> Vec<u8> {
+        let mut data = vec![
+            0x4E, 0x45, 0x53, 0x1A, 1, 0, 0, flags_7, 0, 0, 0, 0, timing, 0, 0, 0,
+        ];
+        data.resize(data.len() + PROGRAM_ROM_PAGE_SIZE, 0);
+
+        data
+    }
+
+    #[test]
+    fn region_is_read_from_the_nes2_timing_field() {
+        let cartridge = Cartridge::new(image(0b1000, 1)).unwrap();
+        assert_eq!(cartridge.region(), Region::Pal);
+
+        let cartridge = Cartridge::new(image(0b1000, 3)).unwrap();
+        assert_eq!(cartridge.region(), Region::Dendy);
+
+        let cartridge = Cartridge::new(image(0, 3)).unwrap();
+        assert_eq!(cartridge.region(), Region::Ntsc);
+    }
+
+    #[test]
+    fn input_devices_are_read_from_the_nes2_expansion_field() {
+        let mut data = image(0b1000, 0);
+        data[15] = 0x08;
+        assert_eq!(
+            Cartridge::new(data.clone()).unwrap().input_devices(),
+            DeviceSetup::Zapper
+        );
+
+        data[7] = 0;
+        assert_eq!(
+            Cartridge::new(data).unwrap().input_devices(),
+            DeviceSetup::StandardControllers
+        );
+    }
+
+    #[test]
+    fn truncated_images_are_rejected() {
+        let mut data = image(0, 0);
+        data.truncate(100);
+
+        assert!(Cartridge::new(data).is_err());
+        assert!(Cartridge::new(vec![0x4E]).is_err());
+    }
+}
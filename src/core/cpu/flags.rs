@@ -1,3 +1,5 @@
+use crate::state::stateful_bitflags;
+
 bitflags! {
     pub struct CpuFlags: u8 {
         const CARRY             = 0b00000001;
@@ -22,3 +24,5 @@ impl CpuFlags {
         Self::default()
     }
 }
+
+stateful_bitflags!(CpuFlags);
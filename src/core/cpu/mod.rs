@@ -1,7 +1,8 @@
 use crate::{
-    core::{Bus, SubComponent, OPCODE_MAP},
+    core::{Bus, Interrupt, InterruptType, SubComponent, INTERRUPT_DESCRIPTOR_TABLE, OPCODE_MAP},
     error::{Error, Result},
     io::{Read, Write},
+    state::stateful,
 };
 use std::fmt::Display;
 
@@ -41,6 +42,19 @@ impl Cpu {
         }
     }
 
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
+    /// Address of the next instruction
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter.get()
+    }
+
     pub fn load_cartridge(&mut self, cartridge: &Cartridge) {
         self.bus.load_cartridge(cartridge);
     }
@@ -63,40 +77,76 @@ impl Cpu {
         F: FnMut(&mut Cpu) -> Result<()>,
     {
         loop {
-            if self.bus.poll_nmi_status().is_some() {
-                // self.interrupt(interrupt::NMI)
-            }
+            self.service_interrupts()?;
 
             callback(self)?;
 
-            let program_counter = self.program_counter.get();
-            let code = self.read_byte(program_counter)?;
-
-            self.program_counter.increment();
-            let program_counter = self.program_counter.get();
-            let opcode = OPCODE_MAP.get(&code).ok_or_else(|| {
-                Error::Unsupported(format!(r#"opcode "{code:#x}" is not supported"#))
-            })?;
-
-            if let CpuMessage::Break = self.handle_opcode(opcode)? {
+            if let CpuMessage::Break = self.execute()? {
                 break;
             }
+        }
 
-            self.bus.tick(opcode.cycles as usize);
+        Ok(())
+    }
 
-            if program_counter == self.program_counter.get() {
-                (0..(opcode.len() - 1) as u16).for_each(|_| self.program_counter.increment())
-            }
+    /// Services a pending interrupt and executes a single instruction. Unlike `run`,
+    /// BRK doesn't stop anything and jumps through the IRQ vector like the hardware
+    pub fn step(&mut self) -> Result<CpuMessage> {
+        self.service_interrupts()?;
+
+        let message = self.execute()?;
+        if let CpuMessage::Break = message {
+            // BRK skips the padding byte that follows it
+            self.program_counter.increment();
+            self.interrupt(&INTERRUPT_DESCRIPTOR_TABLE[&InterruptType::BRK])?;
+            self.bus.run_dma()?;
+        }
+
+        Ok(message)
+    }
+
+    fn service_interrupts(&mut self) -> Result<()> {
+        if self.bus.poll_nmi_status().is_some() {
+            self.interrupt(&INTERRUPT_DESCRIPTOR_TABLE[&InterruptType::NMI])?;
+        } else if self.bus.poll_irq_status() && !self.status.contains(CpuFlags::INTERRUPT_DISABLE) {
+            self.interrupt(&INTERRUPT_DESCRIPTOR_TABLE[&InterruptType::IRQ])?;
         }
 
         Ok(())
     }
 
+    /// Fetches and executes the instruction at the program counter, BRK is left for
+    /// the caller to handle
+    fn execute(&mut self) -> Result<CpuMessage> {
+        let program_counter = self.program_counter.get();
+        let code = self.read_byte(program_counter)?;
+
+        self.program_counter.increment();
+        let program_counter = self.program_counter.get();
+        let opcode = OPCODE_MAP
+            .get(&code)
+            .ok_or_else(|| Error::Unsupported(format!(r#"opcode "{code:#x}" is not supported"#)))?;
+
+        if let CpuMessage::Break = self.handle_opcode(opcode)? {
+            return Ok(CpuMessage::Break);
+        }
+
+        self.bus.tick(opcode.cycles as usize);
+        self.bus.run_dma()?;
+
+        if program_counter == self.program_counter.get() {
+            (0..(opcode.len() - 1) as u16).for_each(|_| self.program_counter.increment())
+        }
+
+        Ok(CpuMessage::Continue)
+    }
+
     pub fn reset(&mut self) -> Result<()> {
         self.register_a.reset();
         self.register_x.reset();
         self.register_y.reset();
-        self.status.reset();
+        self.stack_pointer.set(STACK_RESET);
+        self.status = CpuFlags::INTERRUPT_DISABLE | CpuFlags::BREAK2;
 
         let start_addr = self.read_word(0xFFFC)?;
         self.program_counter.set(start_addr);
@@ -104,6 +154,24 @@ impl Cpu {
         Ok(())
     }
 
+    /// Pushes the program counter and status, then jumps through the interrupt's vector
+    fn interrupt(&mut self, interrupt: &Interrupt) -> Result<()> {
+        self.stack_push_word(self.program_counter.get())?;
+
+        let mut flags = self.status;
+        flags.set(CpuFlags::BREAK, interrupt.b_flag_mask() & 0b0001_0000 != 0);
+        flags.set(CpuFlags::BREAK2, interrupt.b_flag_mask() & 0b0010_0000 != 0);
+        self.stack_push_byte(flags.bits())?;
+
+        self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+        self.bus.tick(interrupt.cpu_cycles() as usize);
+
+        let vector = self.read_word(interrupt.vector_address())?;
+        self.program_counter.set(vector);
+
+        Ok(())
+    }
+
     fn set_carry_flag(&mut self) {
         self.status.insert(CpuFlags::CARRY);
     }
@@ -163,3 +231,13 @@ impl Display for Cpu {
         write!(f, "{}", message)
     }
 }
+
+// the bus is saved on its own
+stateful!(Cpu {
+    register_a,
+    register_x,
+    register_y,
+    program_counter,
+    stack_pointer,
+    status,
+});
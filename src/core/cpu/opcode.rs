@@ -771,17 +771,14 @@ impl Cpu {
         Ok(())
     }
 
-    fn stack_push_byte(&mut self, value: u8) -> Result<()> {
-        self.write_byte(
-            (STACK_START_ADDR as u16) + self.stack_pointer.get() as u16,
-            value,
-        )?;
+    pub(super) fn stack_push_byte(&mut self, value: u8) -> Result<()> {
+        self.write_byte(STACK_START_ADDR + self.stack_pointer.get() as u16, value)?;
         self.stack_pointer.decrement();
 
         Ok(())
     }
 
-    fn stack_push_word(&mut self, value: u16) -> Result<()> {
+    pub(super) fn stack_push_word(&mut self, value: u16) -> Result<()> {
         let hi = value >> 8;
         let lo = value & 0xFF;
 
@@ -792,7 +789,7 @@ impl Cpu {
     fn stack_pop_byte(&mut self) -> Result<u8> {
         self.stack_pointer.increment();
 
-        self.read_byte(STACK_START_ADDR as u16 + self.stack_pointer.get() as u16)
+        self.read_byte(STACK_START_ADDR + self.stack_pointer.get() as u16)
     }
 
     fn stack_pop_word(&mut self) -> Result<u16> {
@@ -883,7 +880,7 @@ impl Cpu {
                 let base = self.read_byte(addr)?;
 
                 let lo = self.read_byte(base as u16)?;
-                let hi = self.read_byte((base as u8).wrapping_add(1) as u16)?;
+                let hi = self.read_byte(base.wrapping_add(1) as u16)?;
                 let deref_base = (hi as u16) << 8 | (lo as u16);
                 let deref = deref_base.wrapping_add(self.register_y.get() as u16);
 
@@ -0,0 +1,82 @@
+use super::SubComponent;
+use crate::state::stateful;
+
+/// CPU cycles the OAM DMA unit halts the CPU for when starting on an even cycle
+pub const OAM_DMA_CYCLES: usize = 513;
+/// CPU cycles a DMC sample fetch halts the CPU for
+pub const DMC_DMA_CYCLES: usize = 4;
+/// CPU cycles a DMC sample fetch adds when it interrupts an OAM DMA
+pub const DMC_DMA_DURING_OAM_CYCLES: usize = 2;
+
+/// Arbitrates the bus between the CPU and the two DMA units. Tracks OAM transfers
+/// requested by the CPU that have yet to be carried out
+#[derive(Debug, Default)]
+pub struct Dma {
+    oam_page: Option<u8>,
+    stalled_cycles: SubComponent<usize>,
+}
+
+impl Dma {
+    /// Queues a copy of `$XX00-$XXFF` into OAM, where `XX` is the given page
+    pub fn request_oam(&mut self, page: u8) {
+        self.oam_page = Some(page);
+    }
+
+    pub fn take_oam_request(&mut self) -> Option<u8> {
+        self.oam_page.take()
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.oam_page.is_some()
+    }
+
+    /// Number of CPU cycles the transfer takes, one alignment cycle is added when
+    /// the transfer starts on an odd CPU cycle
+    pub fn oam_stall_cycles(cpu_cycles: usize) -> usize {
+        OAM_DMA_CYCLES + (cpu_cycles & 1)
+    }
+
+    /// A DMC fetch that lands in the middle of an OAM transfer reuses its halt and
+    /// alignment cycles
+    pub fn dmc_stall_cycles(during_oam: bool) -> usize {
+        match during_oam {
+            true => DMC_DMA_DURING_OAM_CYCLES,
+            false => DMC_DMA_CYCLES,
+        }
+    }
+
+    pub fn record_stall(&mut self, cycles: usize) {
+        self.stalled_cycles.wrapping_add(cycles);
+    }
+
+    /// Total CPU cycles stolen by DMA transfers so far
+    pub fn stalled_cycles(&self) -> usize {
+        self.stalled_cycles.get()
+    }
+}
+
+stateful!(Dma {
+    oam_page,
+    stalled_cycles,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::Dma;
+
+    #[test]
+    fn oam_dma_takes_an_extra_cycle_when_odd_aligned() {
+        assert_eq!(Dma::oam_stall_cycles(10), 513);
+        assert_eq!(Dma::oam_stall_cycles(11), 514);
+    }
+
+    #[test]
+    fn oam_request_is_consumed_once() {
+        let mut dma = Dma::default();
+        dma.request_oam(0x02);
+
+        assert!(dma.is_pending());
+        assert_eq!(dma.take_oam_request(), Some(0x02));
+        assert_eq!(dma.take_oam_request(), None);
+    }
+}
@@ -0,0 +1,123 @@
+use super::{InputDevice, InputState};
+use crate::{core::Ppu, state::stateful};
+
+/// Host-side state of the Vaus paddle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaddleState {
+    /// Potentiometer reading, games expect roughly `0x62-0xF2`
+    pub position: u8,
+    pub button: bool,
+}
+
+impl Default for PaddleState {
+    fn default() -> Self {
+        Self {
+            position: 0xAA,
+            button: false,
+        }
+    }
+}
+
+/// The Arkanoid Vaus controller. The potentiometer is latched on strobe and shifted
+/// out inverted, most significant bit first. The NES version answers on `$4017`
+/// bits 3 (button) and 4 (data), the Famicom one sits in the expansion port and uses
+/// bit 1 of `$4016` (button) and `$4017` (data).
+#[derive(Debug, Default, Clone)]
+pub struct Arkanoid {
+    famicom: bool,
+    state: PaddleState,
+    strobe: bool,
+    shift_register: u8,
+}
+
+impl Arkanoid {
+    pub fn nes() -> Self {
+        Self::default()
+    }
+
+    pub fn famicom() -> Self {
+        Self {
+            famicom: true,
+            ..Self::default()
+        }
+    }
+
+    fn data_bit(&mut self) -> u8 {
+        if self.strobe {
+            self.shift_register = !self.state.position;
+        }
+
+        let bit = self.shift_register >> 7;
+        if !self.strobe {
+            self.shift_register <<= 1;
+        }
+
+        bit
+    }
+}
+
+impl InputDevice for Arkanoid {
+    fn set_input(&mut self, input: &InputState, _port: usize) {
+        self.state = input.paddle();
+    }
+
+    fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.shift_register = !self.state.position;
+        }
+    }
+
+    fn read(&mut self, port: usize, _ppu: &Ppu) -> u8 {
+        let button = self.state.button as u8;
+
+        match (self.famicom, port) {
+            (false, _) => (self.data_bit() << 4) | (button << 3),
+            (true, 0) => button << 1,
+            (true, _) => self.data_bit() << 1,
+        }
+    }
+}
+
+stateful!(PaddleState { position, button });
+
+stateful!(Arkanoid {
+    famicom,
+    state,
+    strobe,
+    shift_register,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::Arkanoid;
+    use crate::core::{
+        input::{InputDevice, InputState},
+        Mirroring, Ppu, Rom,
+    };
+    use crate::rom;
+
+    #[test]
+    fn position_is_shifted_out_inverted_msb_first() {
+        let ppu = Ppu::new(rom![0; 16], Mirroring::Vertical);
+        let mut input = InputState::default();
+        input.set_paddle(0b1010_0000, true);
+
+        let mut vaus = Arkanoid::nes();
+        vaus.set_input(&input, 1);
+        vaus.write(1);
+        vaus.write(0);
+
+        let bits = (0..4).map(|_| vaus.read(1, &ppu)).collect::<Vec<_>>();
+        assert_eq!(bits, [0b01000, 0b11000, 0b01000, 0b11000]);
+
+        let mut vaus = Arkanoid::famicom();
+        vaus.set_input(&input, 1);
+        vaus.write(1);
+        vaus.write(0);
+
+        assert_eq!(vaus.read(0, &ppu), 0b10);
+        assert_eq!(vaus.read(1, &ppu), 0b00);
+        assert_eq!(vaus.read(1, &ppu), 0b10);
+    }
+}
@@ -0,0 +1,123 @@
+use super::{Buttons, InputDevice, InputState, Joypad, PORTS};
+use crate::{core::Ppu, state::stateful};
+
+/// Bits the Four Score shifts out after the two controllers, in read order, so
+/// games can tell it apart from a pair of plain controllers
+const SIGNATURES: [u8; PORTS] = [0b0001_0000, 0b0010_0000];
+/// Reads after which only 1s are returned, two controllers and a signature byte
+const REPORT_LENGTH: usize = 24;
+
+/// One half of the NES Four Score / NES Satellite. Plugged into both ports, the
+/// port 0 half reports players 1 and 3 and the port 1 half players 2 and 4.
+#[derive(Debug, Default, Clone)]
+pub struct FourScore {
+    buttons: [Buttons; 2],
+    strobe: bool,
+    index: usize,
+}
+
+impl InputDevice for FourScore {
+    fn set_input(&mut self, input: &InputState, port: usize) {
+        self.buttons = [input.buttons(port), input.buttons(port + PORTS)];
+    }
+
+    fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.index = 0;
+        }
+    }
+
+    fn read(&mut self, port: usize, _ppu: &Ppu) -> u8 {
+        let bit = match self.index {
+            0..=7 => self.buttons[0].bits() >> self.index,
+            8..=15 => self.buttons[1].bits() >> (self.index - 8),
+            16..=23 => SIGNATURES[port] >> (23 - self.index),
+            _ => 1,
+        };
+
+        if !self.strobe {
+            self.index = (self.index + 1).min(REPORT_LENGTH);
+        }
+
+        bit & 1
+    }
+}
+
+/// Famicom expansion port adapter for players 3 and 4, their controllers are read
+/// in parallel with the built-in ones through bit 1 of `$4016` and `$4017`
+#[derive(Debug, Default, Clone)]
+pub struct FamicomFourPlayer {
+    joypads: [Joypad; PORTS],
+}
+
+impl InputDevice for FamicomFourPlayer {
+    fn set_input(&mut self, input: &InputState, _port: usize) {
+        for (port, joypad) in self.joypads.iter_mut().enumerate() {
+            joypad.set_buttons(input.buttons(port + PORTS));
+        }
+    }
+
+    fn write(&mut self, value: u8) {
+        self.joypads
+            .iter_mut()
+            .for_each(|joypad| joypad.write(value));
+    }
+
+    fn read(&mut self, port: usize, _ppu: &Ppu) -> u8 {
+        self.joypads[port].read() << 1
+    }
+}
+
+stateful!(FourScore {
+    buttons,
+    strobe,
+    index,
+});
+
+stateful!(FamicomFourPlayer { joypads });
+
+#[cfg(test)]
+mod tests {
+    use super::{FamicomFourPlayer, FourScore};
+    use crate::core::{
+        input::{Buttons, InputDevice, InputState},
+        Mirroring, Ppu, Rom,
+    };
+    use crate::rom;
+
+    fn read_bits(device: &mut dyn InputDevice, port: usize, reads: usize) -> Vec<u8> {
+        let ppu = Ppu::new(rom![0; 16], Mirroring::Vertical);
+        device.write(1);
+        device.write(0);
+
+        (0..reads).map(|_| device.read(port, &ppu)).collect()
+    }
+
+    #[test]
+    fn four_score_reports_both_players_then_its_signature() {
+        let mut input = InputState::default();
+        input.set_buttons(1, Buttons::A);
+        input.set_buttons(3, Buttons::RIGHT);
+
+        let mut four_score = FourScore::default();
+        four_score.set_input(&input, 1);
+
+        let bits = read_bits(&mut four_score, 1, 26);
+        assert_eq!(bits[..8], [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(bits[8..16], [0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(bits[16..24], [0, 0, 1, 0, 0, 0, 0, 0]);
+        assert_eq!(bits[24..], [1, 1]);
+    }
+
+    #[test]
+    fn famicom_adapter_reports_on_bit_1() {
+        let mut input = InputState::default();
+        input.set_buttons(2, Buttons::B);
+
+        let mut adapter = FamicomFourPlayer::default();
+        adapter.set_input(&input, 0);
+
+        assert_eq!(read_bits(&mut adapter, 0, 3), [0, 0b10, 0]);
+    }
+}
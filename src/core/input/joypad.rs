@@ -0,0 +1,111 @@
+use super::{InputDevice, InputState};
+use crate::{
+    core::Ppu,
+    state::{stateful, stateful_bitflags},
+};
+
+bitflags! {
+    /// Buttons of a standard controller, in the order they are shifted out
+    #[derive(Default)]
+    pub struct Buttons: u8 {
+        const A      = 0b00000001;
+        const B      = 0b00000010;
+        const SELECT = 0b00000100;
+        const START  = 0b00001000;
+        const UP     = 0b00010000;
+        const DOWN   = 0b00100000;
+        const LEFT   = 0b01000000;
+        const RIGHT  = 0b10000000;
+    }
+}
+
+/// The standard controller, a 4021 shift register latching the buttons while the
+/// strobe is high
+#[derive(Debug, Default, Clone)]
+pub struct Joypad {
+    buttons: Buttons,
+    strobe: bool,
+    shift_register: u8,
+}
+
+impl Joypad {
+    pub fn buttons(&self) -> Buttons {
+        self.buttons
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.buttons = buttons;
+        if self.strobe {
+            self.shift_register = buttons.bits();
+        }
+    }
+
+    /// Bit 0 of a `$4016` write drives the strobe line of both ports
+    pub fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.shift_register = self.buttons.bits();
+        }
+    }
+
+    /// Shifts out the next button, official controllers report 1 once all eight
+    /// have been read
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            return self.buttons.bits() & 1;
+        }
+
+        let bit = self.shift_register & 1;
+        self.shift_register = (self.shift_register >> 1) | 0b1000_0000;
+
+        bit
+    }
+}
+
+impl InputDevice for Joypad {
+    fn set_input(&mut self, input: &InputState, port: usize) {
+        self.set_buttons(input.buttons(port));
+    }
+
+    fn write(&mut self, value: u8) {
+        Joypad::write(self, value);
+    }
+
+    fn read(&mut self, _port: usize, _ppu: &Ppu) -> u8 {
+        Joypad::read(self)
+    }
+}
+
+stateful_bitflags!(Buttons);
+
+stateful!(Joypad {
+    buttons,
+    strobe,
+    shift_register,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::{Buttons, Joypad};
+
+    #[test]
+    fn buttons_are_shifted_out_in_order_then_ones() {
+        let mut joypad = Joypad::default();
+        joypad.set_buttons(Buttons::A | Buttons::START | Buttons::RIGHT);
+        joypad.write(1);
+        joypad.write(0);
+
+        let bits = (0..10).map(|_| joypad.read()).collect::<Vec<_>>();
+        assert_eq!(bits, [1, 0, 0, 1, 0, 0, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn strobe_keeps_reporting_a() {
+        let mut joypad = Joypad::default();
+        joypad.set_buttons(Buttons::A);
+        joypad.write(1);
+
+        assert_eq!(joypad.read(), 1);
+        assert_eq!(joypad.read(), 1);
+    }
+}
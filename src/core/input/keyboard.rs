@@ -0,0 +1,84 @@
+use super::{InputDevice, InputState};
+use crate::{core::Ppu, state::stateful};
+
+/// Rows of the key matrix, each with two columns of four keys
+pub const KEYBOARD_ROWS: usize = 9;
+/// What the keyboard reports once the scan runs past the last row
+const NO_KEYS: u8 = 0b1_1110;
+
+/// The Family BASIC keyboard on the Famicom expansion port. `$4016` writes reset
+/// the scan (bit 0), select the column (bit 1) and enable the keyboard (bit 2); the
+/// row advances whenever the column goes from 1 back to 0. `$4017` bits 1-4 read
+/// the four keys of the selected row and column, pressed keys read back as 0.
+#[derive(Debug, Default, Clone)]
+pub struct FamilyBasicKeyboard {
+    keys: [u8; KEYBOARD_ROWS],
+    row: usize,
+    column: usize,
+    enabled: bool,
+}
+
+impl InputDevice for FamilyBasicKeyboard {
+    fn set_input(&mut self, input: &InputState, _port: usize) {
+        self.keys = input.keyboard();
+    }
+
+    fn write(&mut self, value: u8) {
+        let column = ((value >> 1) & 1) as usize;
+        self.enabled = value & 0b100 != 0;
+
+        if value & 1 != 0 {
+            self.row = 0;
+        } else if self.column == 1 && column == 0 {
+            self.row = (self.row + 1).min(KEYBOARD_ROWS);
+        }
+
+        self.column = column;
+    }
+
+    fn read(&mut self, port: usize, _ppu: &Ppu) -> u8 {
+        if port == 0 || !self.enabled {
+            return 0;
+        }
+
+        match self.keys.get(self.row) {
+            Some(keys) => !((keys >> (self.column * 4)) << 1) & NO_KEYS,
+            None => NO_KEYS,
+        }
+    }
+}
+
+stateful!(FamilyBasicKeyboard {
+    keys,
+    row,
+    column,
+    enabled,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::FamilyBasicKeyboard;
+    use crate::core::{
+        input::{InputDevice, InputState},
+        Mirroring, Ppu, Rom,
+    };
+    use crate::rom;
+
+    #[test]
+    fn matrix_is_scanned_row_by_row() {
+        let ppu = Ppu::new(rom![0; 16], Mirroring::Vertical);
+        let mut input = InputState::default();
+        input.set_keyboard_row(1, 0b0010_0001);
+
+        let mut keyboard = FamilyBasicKeyboard::default();
+        keyboard.set_input(&input, 2);
+
+        keyboard.write(0b101);
+        assert_eq!(keyboard.read(1, &ppu), 0b1_1110);
+        keyboard.write(0b110);
+        keyboard.write(0b100);
+        assert_eq!(keyboard.read(1, &ppu), 0b1_1100);
+        keyboard.write(0b110);
+        assert_eq!(keyboard.read(1, &ppu), 0b1_1010);
+    }
+}
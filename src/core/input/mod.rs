@@ -0,0 +1,182 @@
+mod arkanoid;
+mod four_score;
+mod joypad;
+mod keyboard;
+mod power_pad;
+mod zapper;
+
+use std::fmt::Debug;
+
+use super::{Palette, Ppu};
+use crate::state::Stateful;
+
+pub use arkanoid::{Arkanoid, PaddleState};
+pub use four_score::{FamicomFourPlayer, FourScore};
+pub use joypad::{Buttons, Joypad};
+pub use keyboard::{FamilyBasicKeyboard, KEYBOARD_ROWS};
+pub use power_pad::{FamilyTrainer, PowerPad};
+pub use zapper::{Zapper, ZapperState};
+
+/// Number of controller ports on the console
+pub const PORTS: usize = 2;
+/// Port number expansion port devices are handed in `InputDevice::set_input`
+pub const EXPANSION_PORT: usize = PORTS;
+/// Players a multitap can connect
+pub const PLAYERS: usize = 4;
+
+/// A device plugged into one of the controller ports or the Famicom expansion port
+pub trait InputDevice: Debug + Stateful {
+    /// Identifies the kind of device in save states
+    fn name(&self) -> &'static str {
+        let name = std::any::type_name::<Self>();
+
+        name.rsplit("::").next().unwrap_or(name)
+    }
+
+    /// Picks up the host's state for the device plugged into `port`
+    fn set_input(&mut self, input: &InputState, port: usize);
+
+    /// Handles a `$4016` write, bit 0 is the strobe shared by both ports
+    fn write(&mut self, value: u8);
+
+    /// Handles a read of `$4016` (port 0) or `$4017` (port 1), returning bits 0-4.
+    /// The PPU is there for devices that look at the picture, such as light guns.
+    /// Expansion port devices see reads of both registers.
+    fn read(&mut self, port: usize, ppu: &Ppu) -> u8;
+
+    /// Hands over the colors the picture is drawn with, for devices that judge the
+    /// brightness of what's on screen
+    fn set_palette(&mut self, _palette: &Palette) {}
+}
+
+/// Host-side snapshot of the controllers, applied to the console once per frame.
+/// Buttons are indexed by player, players 3 and 4 are only read through a multitap.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InputState {
+    buttons: [Buttons; PLAYERS],
+    zapper: ZapperState,
+    paddle: PaddleState,
+    /// Power Pad buttons, bit `n - 1` holds button `n`
+    power_pad: u16,
+    /// Family BASIC key matrix, the low nibble of a row is column 0
+    keyboard: [u8; KEYBOARD_ROWS],
+}
+
+impl InputState {
+    pub fn buttons(&self, player: usize) -> Buttons {
+        self.buttons[player]
+    }
+
+    pub fn set_buttons(&mut self, player: usize, buttons: Buttons) {
+        self.buttons[player] = buttons;
+    }
+
+    pub fn press(&mut self, player: usize, buttons: Buttons) {
+        self.buttons[player].insert(buttons);
+    }
+
+    pub fn release(&mut self, player: usize, buttons: Buttons) {
+        self.buttons[player].remove(buttons);
+    }
+
+    pub fn zapper(&self) -> ZapperState {
+        self.zapper
+    }
+
+    /// Aims the light gun at a screen position, `None` points it away from the screen
+    pub fn set_zapper(&mut self, position: Option<(usize, usize)>, trigger: bool) {
+        self.zapper = ZapperState { position, trigger };
+    }
+
+    pub fn paddle(&self) -> PaddleState {
+        self.paddle
+    }
+
+    pub fn set_paddle(&mut self, position: u8, button: bool) {
+        self.paddle = PaddleState { position, button };
+    }
+
+    pub fn power_pad(&self) -> u16 {
+        self.power_pad
+    }
+
+    /// Sets the Power Pad buttons, bit `n - 1` holds button `n`
+    pub fn set_power_pad(&mut self, buttons: u16) {
+        self.power_pad = buttons & 0x0FFF;
+    }
+
+    pub fn keyboard(&self) -> [u8; KEYBOARD_ROWS] {
+        self.keyboard
+    }
+
+    /// Sets the pressed keys of a row of the Family BASIC keyboard matrix, the low
+    /// nibble holds column 0 and the high nibble column 1
+    pub fn set_keyboard_row(&mut self, row: usize, keys: u8) {
+        self.keyboard[row] = keys;
+    }
+}
+
+/// Devices plugged into the two ports and the Famicom expansion port
+pub type Devices = ([Box<dyn InputDevice>; PORTS], Option<Box<dyn InputDevice>>);
+
+/// Input peripherals a game can be set up with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DeviceSetup {
+    #[default]
+    StandardControllers,
+    FourScore,
+    FamicomFourPlayer,
+    /// A Zapper in port 1, next to a controller in port 0
+    Zapper,
+    TwoZappers,
+    PowerPad,
+    FamilyTrainer,
+    ArkanoidNes,
+    ArkanoidFamicom,
+    FamilyBasicKeyboard,
+}
+
+impl DeviceSetup {
+    /// Maps the NES 2.0 default expansion device field, devices that aren't
+    /// emulated fall back to standard controllers
+    pub fn from_nes2_expansion_device(value: u8) -> Self {
+        match value & 0x3F {
+            0x02 => Self::FourScore,
+            0x03 => Self::FamicomFourPlayer,
+            0x08 => Self::Zapper,
+            0x09 => Self::TwoZappers,
+            0x0B | 0x0C => Self::PowerPad,
+            0x0D | 0x0E => Self::FamilyTrainer,
+            0x0F => Self::ArkanoidNes,
+            0x10 => Self::ArkanoidFamicom,
+            0x23 => Self::FamilyBasicKeyboard,
+            _ => Self::StandardControllers,
+        }
+    }
+
+    pub fn devices(&self) -> Devices {
+        let joypad = || Box::<Joypad>::default() as Box<dyn InputDevice>;
+
+        match self {
+            Self::StandardControllers => ([joypad(), joypad()], None),
+            Self::FourScore => (
+                [Box::<FourScore>::default(), Box::<FourScore>::default()],
+                None,
+            ),
+            Self::FamicomFourPlayer => (
+                [joypad(), joypad()],
+                Some(Box::<FamicomFourPlayer>::default()),
+            ),
+            Self::Zapper => ([joypad(), Box::<Zapper>::default()], None),
+            Self::TwoZappers => ([Box::<Zapper>::default(), Box::<Zapper>::default()], None),
+            Self::PowerPad => ([joypad(), Box::<PowerPad>::default()], None),
+            Self::FamilyTrainer => ([joypad(), joypad()], Some(Box::<FamilyTrainer>::default())),
+            Self::ArkanoidNes => ([joypad(), Box::new(Arkanoid::nes())], None),
+            Self::ArkanoidFamicom => ([joypad(), joypad()], Some(Box::new(Arkanoid::famicom()))),
+            Self::FamilyBasicKeyboard => (
+                [joypad(), joypad()],
+                Some(Box::<FamilyBasicKeyboard>::default()),
+            ),
+        }
+    }
+}
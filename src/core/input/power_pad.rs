@@ -0,0 +1,144 @@
+use super::{InputDevice, InputState};
+use crate::{core::Ppu, state::stateful};
+
+/// Buttons, numbered from 1, shifted out through bit 3 of the port
+const SERIAL_ORDER_D3: [usize; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+/// Buttons shifted out through bit 4, which reads 1 once they are done
+const SERIAL_ORDER_D4: [usize; 4] = [4, 3, 12, 8];
+/// Buttons read through `$4017` bits 1-4 for each row selected by a `$4016` write
+const MATRIX_ROWS: [[usize; 4]; 3] = [[12, 11, 10, 9], [8, 7, 6, 5], [4, 3, 2, 1]];
+
+fn pressed(buttons: u16, button: usize) -> u8 {
+    (buttons >> (button - 1)) as u8 & 1
+}
+
+/// The NES Power Pad mat, twelve buttons latched on strobe and shifted out through
+/// bits 3 and 4 of its port. Bit `n - 1` of the host state holds button `n`.
+#[derive(Debug, Default, Clone)]
+pub struct PowerPad {
+    buttons: u16,
+    strobe: bool,
+    index: usize,
+}
+
+impl InputDevice for PowerPad {
+    fn set_input(&mut self, input: &InputState, _port: usize) {
+        self.buttons = input.power_pad();
+    }
+
+    fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.index = 0;
+        }
+    }
+
+    fn read(&mut self, _port: usize, _ppu: &Ppu) -> u8 {
+        let d3 = SERIAL_ORDER_D3
+            .get(self.index)
+            .map_or(1, |&button| pressed(self.buttons, button));
+        let d4 = SERIAL_ORDER_D4
+            .get(self.index)
+            .map_or(1, |&button| pressed(self.buttons, button));
+
+        if !self.strobe {
+            self.index = (self.index + 1).min(SERIAL_ORDER_D3.len());
+        }
+
+        (d3 << 3) | (d4 << 4)
+    }
+}
+
+/// The Famicom Family Trainer mat on the expansion port. The three low bits of a
+/// `$4016` write select rows of the button matrix, pressed buttons read back as 0.
+#[derive(Debug, Default, Clone)]
+pub struct FamilyTrainer {
+    buttons: u16,
+    select: u8,
+}
+
+impl InputDevice for FamilyTrainer {
+    fn set_input(&mut self, input: &InputState, _port: usize) {
+        self.buttons = input.power_pad();
+    }
+
+    fn write(&mut self, value: u8) {
+        self.select = value & 0b111;
+    }
+
+    fn read(&mut self, port: usize, _ppu: &Ppu) -> u8 {
+        if port == 0 {
+            return 0;
+        }
+
+        let pressed = MATRIX_ROWS
+            .iter()
+            .enumerate()
+            .filter(|(row, _)| self.select & (1 << row) == 0)
+            .flat_map(|(_, buttons)| buttons.iter().enumerate())
+            .fold(0, |bits, (bit, &button)| {
+                bits | pressed(self.buttons, button) << bit
+            });
+
+        !(pressed << 1) & 0b1_1110
+    }
+}
+
+stateful!(PowerPad {
+    buttons,
+    strobe,
+    index,
+});
+
+stateful!(FamilyTrainer { buttons, select });
+
+#[cfg(test)]
+mod tests {
+    use super::{FamilyTrainer, PowerPad};
+    use crate::core::{
+        input::{InputDevice, InputState},
+        Mirroring, Ppu, Rom,
+    };
+    use crate::rom;
+
+    fn input(buttons: &[usize]) -> InputState {
+        let mut input = InputState::default();
+        input.set_power_pad(
+            buttons
+                .iter()
+                .fold(0, |bits, button| bits | 1 << (button - 1)),
+        );
+        input
+    }
+
+    #[test]
+    fn power_pad_shifts_out_both_streams() {
+        let ppu = Ppu::new(rom![0; 16], Mirroring::Vertical);
+        let mut pad = PowerPad::default();
+        pad.set_input(&input(&[1, 3, 7]), 1);
+        pad.write(1);
+        pad.write(0);
+
+        let bits = (0..9).map(|_| pad.read(1, &ppu)).collect::<Vec<_>>();
+        assert_eq!(
+            bits,
+            [0b00000, 0b11000, 0, 0, 0b10000, 0b10000, 0b10000, 0b11000, 0b11000]
+        );
+    }
+
+    #[test]
+    fn family_trainer_scans_the_selected_row() {
+        let ppu = Ppu::new(rom![0; 16], Mirroring::Vertical);
+        let mut mat = FamilyTrainer::default();
+        mat.set_input(&input(&[1, 6]), 2);
+
+        mat.write(0b011);
+        assert_eq!(mat.read(1, &ppu), 0b0_1110);
+
+        mat.write(0b101);
+        assert_eq!(mat.read(1, &ppu), 0b1_0110);
+
+        mat.write(0b111);
+        assert_eq!(mat.read(1, &ppu), 0b1_1110);
+    }
+}
@@ -0,0 +1,136 @@
+use super::{InputDevice, InputState};
+use crate::{
+    core::{BuiltinPalette, Frame, Palette, Ppu, FRAME_HEIGHT, FRAME_WIDTH},
+    state::stateful,
+};
+
+/// Scanlines a lit pixel keeps the photodiode triggered after the beam drew it
+const LIGHT_PERSISTENCE_SCANLINES: usize = 26;
+/// Pixels around the aimed position the sensor picks light up from
+const SENSOR_RADIUS: usize = 2;
+/// Luma, out of 255, a pixel needs for the sensor to register it
+const BRIGHTNESS_THRESHOLD: u32 = 0xC0;
+
+const LIGHT_NOT_DETECTED: u8 = 0b0000_1000;
+const TRIGGER_PULLED: u8 = 0b0001_0000;
+
+/// Host-side state of the light gun
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ZapperState {
+    /// Screen position the gun points at, `None` when aimed away from the screen
+    pub position: Option<(usize, usize)>,
+    pub trigger: bool,
+}
+
+/// The NES Zapper light gun. The photodiode reports light when the pixels around
+/// the aimed position were drawn bright by the beam within the last few scanlines.
+#[derive(Debug, Clone)]
+pub struct Zapper {
+    palette: Palette,
+    state: ZapperState,
+}
+
+impl Default for Zapper {
+    fn default() -> Self {
+        Self {
+            palette: Palette::from(BuiltinPalette::default()),
+            state: ZapperState::default(),
+        }
+    }
+}
+
+impl Zapper {
+    /// Whether the sensor sees light with the beam on `scanline` of `frame`
+    fn senses_light(&self, frame: &Frame, scanline: usize) -> bool {
+        let Some((x, y)) = self.state.position else {
+            return false;
+        };
+
+        let lines = y.saturating_sub(SENSOR_RADIUS)..=(y + SENSOR_RADIUS).min(FRAME_HEIGHT - 1);
+        let columns = x.saturating_sub(SENSOR_RADIUS)..=(x + SENSOR_RADIUS).min(FRAME_WIDTH - 1);
+
+        lines
+            .filter(|&line| line < scanline && scanline - line <= LIGHT_PERSISTENCE_SCANLINES)
+            .any(|line| {
+                columns
+                    .clone()
+                    .any(|column| self.is_bright(frame.get_pixel(column, line)))
+            })
+    }
+
+    fn is_bright(&self, pixel: u16) -> bool {
+        let [r, g, b] = self.palette.color(pixel).map(|channel| channel as u32);
+
+        (r * 299 + g * 587 + b * 114) / 1000 >= BRIGHTNESS_THRESHOLD
+    }
+}
+
+impl InputDevice for Zapper {
+    fn set_input(&mut self, input: &InputState, _port: usize) {
+        self.state = input.zapper();
+    }
+
+    fn write(&mut self, _value: u8) {}
+
+    fn read(&mut self, _port: usize, ppu: &Ppu) -> u8 {
+        let light = self.senses_light(ppu.frame(), ppu.scanline() as usize);
+
+        match (light, self.state.trigger) {
+            (true, true) => TRIGGER_PULLED,
+            (true, false) => 0,
+            (false, true) => LIGHT_NOT_DETECTED | TRIGGER_PULLED,
+            (false, false) => LIGHT_NOT_DETECTED,
+        }
+    }
+
+    fn set_palette(&mut self, palette: &Palette) {
+        self.palette = palette.clone();
+    }
+}
+
+stateful!(ZapperState { position, trigger });
+
+stateful!(Zapper { state });
+
+#[cfg(test)]
+mod tests {
+    use super::Zapper;
+    use crate::core::{
+        input::{InputDevice, InputState},
+        Frame, Palette,
+    };
+
+    #[test]
+    fn light_is_sensed_only_shortly_after_the_beam_passes() {
+        let mut frame = Frame::default();
+        frame.set_pixel(100, 50, 0x30);
+
+        let mut input = InputState::default();
+        input.set_zapper(Some((101, 51)), true);
+        let mut zapper = Zapper::default();
+        zapper.set_input(&input, 1);
+
+        assert!(!zapper.senses_light(&frame, 50));
+        assert!(zapper.senses_light(&frame, 51));
+        assert!(zapper.senses_light(&frame, 76));
+        assert!(!zapper.senses_light(&frame, 77));
+
+        frame.set_pixel(100, 50, 0x0F);
+        assert!(!zapper.senses_light(&frame, 51));
+    }
+
+    #[test]
+    fn brightness_follows_the_palette_the_picture_is_drawn_with() {
+        let mut frame = Frame::default();
+        frame.set_pixel(100, 50, 0x0F);
+
+        let mut input = InputState::default();
+        input.set_zapper(Some((100, 50)), false);
+        let mut zapper = Zapper::default();
+        zapper.set_input(&input, 1);
+        assert!(!zapper.senses_light(&frame, 51));
+
+        zapper.set_palette(&Palette::new(&[0xFF; 64 * 3]).unwrap());
+        assert!(zapper.senses_light(&frame, 51));
+    }
+}
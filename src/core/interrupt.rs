@@ -1,13 +1,19 @@
 use std::collections::HashMap;
 
 lazy_static! {
-    pub static ref INTERRUPT_DESCRIPTOR_TABLE: HashMap<InterruptType, Interrupt> =
-        HashMap::from([(InterruptType::NMI, Interrupt::new(0xFFFA, 0b0010_0000, 2))]);
+    pub static ref INTERRUPT_DESCRIPTOR_TABLE: HashMap<InterruptType, Interrupt> = HashMap::from([
+        (InterruptType::NMI, Interrupt::new(0xFFFA, 0b0010_0000, 7)),
+        (InterruptType::IRQ, Interrupt::new(0xFFFE, 0b0010_0000, 7)),
+        (InterruptType::BRK, Interrupt::new(0xFFFE, 0b0011_0000, 7)),
+    ]);
 }
 
 #[derive(PartialEq, Eq, Hash)]
 pub enum InterruptType {
     NMI,
+    IRQ,
+    /// Software interrupt raised by the BRK instruction
+    BRK,
 }
 
 #[derive(PartialEq, Eq)]
@@ -25,4 +31,16 @@ impl Interrupt {
             cpu_cycles,
         }
     }
+
+    pub fn vector_address(&self) -> u16 {
+        self.vector_address
+    }
+
+    pub fn b_flag_mask(&self) -> u8 {
+        self.b_flag_mask
+    }
+
+    pub fn cpu_cycles(&self) -> u8 {
+        self.cpu_cycles
+    }
 }
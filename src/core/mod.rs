@@ -1,21 +1,28 @@
 mod addressing_mode;
+pub mod apu;
 mod bus;
 mod cartridge;
 pub mod cpu;
+mod dma;
+pub mod input;
 mod interrupt;
 pub mod opcode;
 mod ppu;
 mod ram;
+mod region;
 mod rom;
 mod sub_component;
 
 pub use addressing_mode::AddressingMode;
-pub use bus::Bus;
+pub use apu::Apu;
+pub use bus::{Bus, PRG_RAM_SIZE};
 pub use cartridge::{Cartridge, Mirroring};
 pub use cpu::Cpu;
+pub use dma::Dma;
 pub use interrupt::{Interrupt, InterruptType, INTERRUPT_DESCRIPTOR_TABLE};
 pub use opcode::{OpCode, OpCodeMap, OPCODE_MAP};
-pub use ppu::Ppu;
-pub use ram::Ram;
+pub use ppu::{BuiltinPalette, Frame, NametableSource, Palette, Ppu, FRAME_HEIGHT, FRAME_WIDTH};
+pub use ram::{Ram, RAM_SIZE};
+pub use region::Region;
 pub use rom::Rom;
 pub use sub_component::SubComponent;
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use super::AddressingMode;
 
 #[allow(unused)]
+#[derive(Debug)]
 pub struct OpCode {
     pub code: u8,
     pub mnemonic: &'static str,
@@ -0,0 +1,71 @@
+use crate::{
+    error::Result,
+    state::{StateReader, StateWriter, Stateful},
+};
+
+pub const FRAME_WIDTH: usize = 256;
+pub const FRAME_HEIGHT: usize = 240;
+
+/// A rendered picture, each pixel holds a 6-bit NES color index in its lower bits
+/// and the three `$2001` emphasis bits above them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pixels: Vec<u16>,
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Self {
+            pixels: vec![0; FRAME_WIDTH * FRAME_HEIGHT],
+        }
+    }
+}
+
+impl Frame {
+    pub fn width(&self) -> usize {
+        FRAME_WIDTH
+    }
+
+    pub fn height(&self) -> usize {
+        FRAME_HEIGHT
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> u16 {
+        self.pixels[y * FRAME_WIDTH + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, value: u16) {
+        self.pixels[y * FRAME_WIDTH + x] = value;
+    }
+
+    pub fn scanline(&self, y: usize) -> &[u16] {
+        &self.pixels[y * FRAME_WIDTH..(y + 1) * FRAME_WIDTH]
+    }
+
+    pub fn scanline_mut(&mut self, y: usize) -> &mut [u16] {
+        &mut self.pixels[y * FRAME_WIDTH..(y + 1) * FRAME_WIDTH]
+    }
+}
+
+impl AsRef<[u16]> for Frame {
+    fn as_ref(&self) -> &[u16] {
+        &self.pixels
+    }
+}
+
+impl Stateful for Frame {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.pixels
+            .iter()
+            .for_each(|pixel| writer.write_bytes(&pixel.to_le_bytes()));
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        let bytes = reader.read_bytes(self.pixels.len() * 2)?;
+        for (pixel, bytes) in self.pixels.iter_mut().zip(bytes.chunks_exact(2)) {
+            *pixel = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+
+        Ok(())
+    }
+}
@@ -1,28 +1,50 @@
+mod frame;
+mod nametable;
+mod open_bus;
+mod palette;
 mod register;
+mod render;
 
-use super::{Mirroring, Ram, Rom, SubComponent};
+use super::{Mirroring, Ram, Region, Rom, SubComponent};
 use crate::{
     error::{Error, Result},
     io::Write,
     rom,
+    state::stateful,
 };
+use nametable::{Nametables, FOUR_SCREEN_VRAM_SIZE};
+use open_bus::OpenBus;
 use register::PpuRegisters;
 
+pub use frame::{Frame, FRAME_HEIGHT, FRAME_WIDTH};
+pub use nametable::NametableSource;
+pub use palette::{BuiltinPalette, Palette};
+
+const VISIBLE_SCANLINES: u16 = 240;
+pub(crate) const DOTS_PER_SCANLINE: usize = 341;
+
 #[derive(Debug)]
 pub struct Ppu {
     pub character_rom: Rom,
     pub mirroring: Mirroring,
+    region: Region,
     registers: PpuRegisters,
     vram: Ram,
+    nametables: Nametables,
 
     oam_address: SubComponent<u8>,
     oam_data: [u8; 256],
     palette_table: [u8; 32],
 
     data_buffer: SubComponent<u8>,
+    open_bus: OpenBus,
+    frame: Frame,
 
     scanline: SubComponent<u16>,
     cycles: SubComponent<usize>,
+    dots: SubComponent<usize>,
+    odd_frame: bool,
+    frames: SubComponent<usize>,
     pub nmi_interrupt: Option<u8>,
 }
 
@@ -37,30 +59,142 @@ impl Ppu {
         Self {
             character_rom,
             mirroring,
+            region: Region::default(),
             registers: PpuRegisters::default(),
             vram: Ram::default(),
+            nametables: Nametables::new(mirroring),
             oam_address: SubComponent::default(),
             oam_data: [0; 64 * 4],
             palette_table: [0; 32],
             data_buffer: SubComponent::default(),
+            open_bus: OpenBus::default(),
+            frame: Frame::default(),
             scanline: SubComponent::default(),
             cycles: SubComponent::default(),
+            dots: SubComponent::default(),
+            odd_frame: false,
+            frames: SubComponent::default(),
             nmi_interrupt: None,
         }
     }
 
-    pub fn mirror_vram_address(&self, addr: u16) -> u16 {
-        let mirrored_vram = addr & 0b10111111111111;
-        let vram_index = mirrored_vram - 0x2000;
-        let name_table = vram_index / 0x400;
+    /// Overrides where a 1K nametable page is read from, as done by mappers which
+    /// can map CHR-ROM or their own RAM as nametables. `None` restores the mapping
+    /// given by the mirroring mode
+    pub fn map_nametable(&mut self, page: usize, source: Option<NametableSource>) {
+        self.nametables.map(page, source);
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
 
-        match (&self.mirroring, name_table) {
-            (Mirroring::Vertical, 2) | (Mirroring::Vertical, 3) => vram_index - 0x800,
-            (Mirroring::Horizontal, 2) => vram_index - 0x400,
-            (Mirroring::Horizontal, 1) => vram_index - 0x400,
-            (Mirroring::Horizontal, 3) => vram_index - 0x800,
-            _ => vram_index,
+    /// Switches the mirroring mode, four-screen brings along the 2K of cartridge
+    /// VRAM its upper pages live in
+    pub fn set_mirroring(&mut self, mirroring: Mirroring) {
+        if mirroring == Mirroring::FourScreen
+            && self.nametables.cartridge_vram().len() < FOUR_SCREEN_VRAM_SIZE
+        {
+            self.nametables.resize_cartridge_vram(FOUR_SCREEN_VRAM_SIZE);
         }
+        self.mirroring = mirroring;
+    }
+
+    /// Resizes the nametable RAM provided by the cartridge
+    pub fn set_cartridge_vram_size(&mut self, size: usize) {
+        self.nametables.resize_cartridge_vram(size);
+    }
+
+    pub fn cartridge_vram(&self) -> &[u8] {
+        self.nametables.cartridge_vram()
+    }
+
+    pub fn cartridge_vram_mut(&mut self) -> &mut [u8] {
+        self.nametables.cartridge_vram_mut()
+    }
+
+    /// Maps `$3F00-$3FFF` onto the 32 byte palette table, `$3F10/$3F14/$3F18/$3F1C`
+    /// are mirrors of `$3F00/$3F04/$3F08/$3F0C`
+    fn palette_address(addr: u16) -> usize {
+        let index = (addr & 0x1F) as usize;
+
+        match index {
+            0x10 | 0x14 | 0x18 | 0x1C => index - 0x10,
+            _ => index,
+        }
+    }
+
+    /// Reads from the pattern tables, carts with less than 8K of CHR read back 0
+    fn read_pattern(&self, addr: u16) -> u8 {
+        self.character_rom
+            .as_ref()
+            .get(addr as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Splits a nametable address into its page and offset, `$3000-$3EFF` mirrors
+    /// `$2000-$2EFF`
+    fn nametable_address(addr: u16) -> (usize, usize) {
+        let index = (addr & 0x0FFF) as usize;
+
+        (index / 0x400, index % 0x400)
+    }
+
+    fn read_nametable(&self, addr: u16) -> u8 {
+        let (page, offset) = Self::nametable_address(addr);
+
+        match self.nametables.source(self.mirroring, page) {
+            NametableSource::Ciram(page) => {
+                self.vram.as_ref()[(page as usize & 1) * 0x400 + offset]
+            }
+            NametableSource::CartridgeVram(page) => {
+                self.nametables.read_cartridge_vram(page, offset)
+            }
+            NametableSource::CharacterRom(bank) => self
+                .character_rom
+                .as_ref()
+                .get(bank as usize * 0x400 + offset)
+                .copied()
+                .unwrap_or(0),
+        }
+    }
+
+    fn write_nametable(&mut self, addr: u16, value: u8) -> Result<()> {
+        let (page, offset) = Self::nametable_address(addr);
+
+        match self.nametables.source(self.mirroring, page) {
+            NametableSource::Ciram(page) => {
+                let index = (page as u16 & 1) * 0x400 + offset as u16;
+                self.vram.write_byte(index, value)?;
+            }
+            NametableSource::CartridgeVram(page) => {
+                self.nametables.write_cartridge_vram(page, offset, value)
+            }
+            NametableSource::CharacterRom(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// The picture being drawn, scanlines above `scanline()` already hold this
+    /// frame's pixels while the ones below still show the previous frame
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+
+    /// Scanline the beam is on, past the visible lines during vblank
+    pub fn scanline(&self) -> u16 {
+        self.scanline.get()
+    }
+
+    /// Dot of the current scanline the beam is on
+    pub fn dot(&self) -> usize {
+        self.cycles.get()
     }
 
     fn increment_vram_addr(&mut self) {
@@ -69,13 +203,23 @@ impl Ppu {
             .add(self.registers.control.vram_address_increment());
     }
 
+    /// Number of frames completed since power on
+    pub fn frame_count(&self) -> usize {
+        self.frames.get()
+    }
+
     pub fn tick(&mut self, cycles: usize) -> bool {
+        self.dots.wrapping_add(cycles);
         self.cycles.wrapping_add(cycles);
-        if self.cycles.get() >= 341 {
-            self.cycles.wrapping_sub(341);
+        while self.cycles.get() >= self.scanline_dots() {
+            self.cycles.wrapping_sub(self.scanline_dots());
+            if self.scanline.get() < VISIBLE_SCANLINES {
+                self.render_scanline(self.scanline.get() as usize);
+            }
+
             self.scanline.increment();
 
-            if self.scanline.get() == 241 {
+            if self.scanline.get() == self.region.vblank_scanline() {
                 self.registers.status.set_vblank_status(true);
                 self.registers.status.set_sprite_zero_hit(false);
 
@@ -84,8 +228,10 @@ impl Ppu {
                 }
             }
 
-            if self.scanline.get() >= 262 {
+            if self.scanline.get() >= self.region.scanlines() {
                 self.scanline.set(0);
+                self.odd_frame = !self.odd_frame;
+                self.frames.increment();
                 self.nmi_interrupt = None;
 
                 self.registers.status.set_sprite_zero_hit(false);
@@ -98,11 +244,24 @@ impl Ppu {
         false
     }
 
+    /// Length of the current scanline, the NTSC pre-render line skips its last dot on
+    /// odd frames while rendering is enabled
+    fn scanline_dots(&self) -> usize {
+        let pre_render = self.scanline.get() == self.region.scanlines() - 1;
+        let rendering = self.registers.mask.show_background() || self.registers.mask.show_sprites();
+
+        match pre_render && self.odd_frame && rendering && self.region.skips_odd_frame_dot() {
+            true => DOTS_PER_SCANLINE - 1,
+            false => DOTS_PER_SCANLINE,
+        }
+    }
+
     pub fn poll_nmi_interrupt(&mut self) -> Option<u8> {
         self.nmi_interrupt.take()
     }
 
     pub fn write_to_ctrl(&mut self, value: u8) {
+        self.refresh_open_bus(value, 0xFF);
         let before_nmi_status = self.registers.control.generate_vblank_nmi();
         self.registers.control.update(value);
 
@@ -115,11 +274,28 @@ impl Ppu {
     }
 
     pub fn write_to_mask(&mut self, value: u8) {
+        self.refresh_open_bus(value, 0xFF);
         self.registers.mask.update(value);
     }
 
+    /// Value seen when reading one of the write-only registers
+    pub fn read_open_bus(&mut self) -> u8 {
+        self.open_bus.read(self.dots.get())
+    }
+
+    fn refresh_open_bus(&mut self, value: u8, mask: u8) {
+        self.open_bus.refresh(value, mask, self.dots.get());
+    }
+
+    /// The status register is read-only, writing to it only charges the I/O latch
+    pub fn write_to_status(&mut self, value: u8) {
+        self.refresh_open_bus(value, 0xFF);
+    }
+
     pub fn read_status(&mut self) -> u8 {
-        let data = self.registers.status.snapshot();
+        // only the upper three bits are driven, the rest come from the I/O latch
+        let data = (self.registers.status.snapshot() & 0xE0) | (self.read_open_bus() & 0x1F);
+        self.refresh_open_bus(data, 0xE0);
         self.registers.status.reset_vblank_status();
         self.registers.address.reset_latch();
         self.registers.scroll.reset_latch();
@@ -128,41 +304,43 @@ impl Ppu {
     }
 
     pub fn write_to_oam_addr(&mut self, value: u8) {
+        self.refresh_open_bus(value, 0xFF);
         self.oam_address.set(value);
     }
 
     pub fn write_to_oam_data(&mut self, value: u8) {
+        self.refresh_open_bus(value, 0xFF);
         self.oam_data[self.oam_address.get() as usize] = value;
         self.oam_address.wrapping_add(1);
     }
 
-    pub fn read_oam_data(&self) -> u8 {
-        self.oam_data[self.oam_address.get() as usize]
+    pub fn read_oam_data(&mut self) -> u8 {
+        let data = self.oam_data[self.oam_address.get() as usize];
+        self.refresh_open_bus(data, 0xFF);
+
+        data
     }
 
     pub fn write_to_scroll(&mut self, value: u8) {
+        self.refresh_open_bus(value, 0xFF);
         self.registers.scroll.write(value);
     }
 
     pub fn write_to_ppu_addr(&mut self, value: u8) {
+        self.refresh_open_bus(value, 0xFF);
         self.registers.address.update(value);
     }
 
     pub fn write_to_data(&mut self, value: u8) -> Result<()> {
+        self.refresh_open_bus(value, 0xFF);
         let addr = self.registers.address.get();
         match addr {
-            0..=0x1fff => println!("attempt to write to chr rom space {}", addr),
-            0x2000..=0x2fff => {
-                let address = self.mirror_vram_address(addr);
-                self.vram.write_byte(address, value)?;
-            }
-            0x3000..=0x3eff => unimplemented!("addr {} shouldn't be used in reallity", addr),
-            0x3f10 | 0x3f14 | 0x3f18 | 0x3f1c => {
-                let add_mirror = addr - 0x10;
-                self.palette_table[(add_mirror - 0x3f00) as usize] = value;
-            }
+            // CHR-ROM is read-only, writes are dropped like on hardware
+            0..=0x1fff => {}
+            // $3000-$3EFF mirrors $2000-$2EFF
+            0x2000..=0x3eff => self.write_nametable(addr, value)?,
             0x3f00..=0x3fff => {
-                self.palette_table[(addr - 0x3f00) as usize] = value;
+                self.palette_table[Self::palette_address(addr)] = value & 0x3F;
             }
             _ => {
                 return Err(Error::Illegal(format!(
@@ -180,41 +358,163 @@ impl Ppu {
         let addr = self.registers.address.get();
         self.increment_vram_addr();
 
-        match addr {
+        let data = match addr {
             0..=0x1fff => {
                 let result = self.data_buffer.get();
-                self.data_buffer
-                    .set(self.character_rom.as_ref()[addr as usize]);
+                self.data_buffer.set(self.read_pattern(addr));
 
-                Ok(result)
+                result
             }
-            0x2000..=0x2fff => {
+            // $3000-$3EFF mirrors $2000-$2EFF
+            0x2000..=0x3eff => {
                 let result = self.data_buffer.get();
-                self.data_buffer
-                    .set(self.vram.as_ref()[self.mirror_vram_address(addr) as usize]);
+                self.data_buffer.set(self.read_nametable(addr));
 
-                Ok(result)
+                result
             }
-            0x3000..=0x3eff => unimplemented!("addr {} shouldn't be used in reallity", addr),
 
-            //Addresses $3F10/$3F14/$3F18/$3F1C are mirrors of $3F00/$3F04/$3F08/$3F0C
-            0x3f10 | 0x3f14 | 0x3f18 | 0x3f1c => {
-                let add_mirror = addr - 0x10;
+            // palette reads skip the buffer, which is instead filled with the nametable
+            // byte "underneath" the palette. Palette entries are 6 bits wide, the upper
+            // two bits come from the I/O latch
+            0x3f00..=0x3fff => {
+                self.data_buffer.set(self.read_nametable(addr));
+
+                let value = self.palette_table[Self::palette_address(addr)] & 0x3F;
+                let data = value | (self.read_open_bus() & 0xC0);
+                self.refresh_open_bus(data, 0x3F);
 
-                Ok(self.palette_table[(add_mirror - 0x3f00) as usize])
+                return Ok(data);
+            }
+            _ => {
+                return Err(Error::Illegal(format!(
+                    "unexpected access to mirrored space {addr}"
+                )))
             }
+        };
 
-            0x3f00..=0x3fff => Ok(self.palette_table[(addr - 0x3f00) as usize]),
-            _ => Err(Error::Illegal(format!(
-                "unexpected access to mirrored space {addr}"
-            ))),
-        }
+        self.refresh_open_bus(data, 0xFF);
+
+        Ok(data)
     }
 
-    fn write_oam_dma(&mut self, data: &[u8; 256]) {
+    pub fn write_oam_dma(&mut self, data: &[u8; 256]) {
         data.iter().for_each(|n| {
             self.oam_data[self.oam_address.get() as usize] = *n;
             self.oam_address.wrapping_add(1);
         })
     }
 }
+
+stateful!(Ppu {
+    mirroring,
+    registers,
+    vram,
+    nametables,
+    oam_address,
+    oam_data,
+    palette_table,
+    data_buffer,
+    open_bus,
+    frame,
+    scanline,
+    cycles,
+    dots,
+    odd_frame,
+    frames,
+    nmi_interrupt,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::{Mirroring, Ppu};
+    use crate::{core::Rom, error::Result, rom};
+
+    fn write_palette(ppu: &mut Ppu, addr: u16, value: u8) -> Result<()> {
+        ppu.write_to_ppu_addr((addr >> 8) as u8);
+        ppu.write_to_ppu_addr(addr as u8);
+        ppu.write_to_data(value)
+    }
+
+    fn read_palette(ppu: &mut Ppu, addr: u16) -> Result<u8> {
+        ppu.write_to_ppu_addr((addr >> 8) as u8);
+        ppu.write_to_ppu_addr(addr as u8);
+        ppu.read_data()
+    }
+
+    #[test]
+    fn palette_ram_is_mirrored_across_3f00_to_3fff() -> Result<()> {
+        let mut ppu = Ppu::default();
+        write_palette(&mut ppu, 0x3F01, 0x2A)?;
+        write_palette(&mut ppu, 0x3F30, 0x0F)?;
+
+        assert_eq!(read_palette(&mut ppu, 0x3FE1)? & 0x3F, 0x2A);
+        assert_eq!(read_palette(&mut ppu, 0x3F10)? & 0x3F, 0x0F);
+        assert_eq!(read_palette(&mut ppu, 0x3F00)? & 0x3F, 0x0F);
+
+        Ok(())
+    }
+
+    #[test]
+    fn greyscale_and_emphasis_apply_to_output_colors() -> Result<()> {
+        let mut ppu = Ppu::default();
+        write_palette(&mut ppu, 0x3F00, 0x2A)?;
+
+        ppu.write_to_mask(0b0010_0001);
+
+        assert_eq!(ppu.output_color(0), 0x20 | 0b001 << 6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_only_registers_and_status_read_back_the_io_latch() {
+        let mut ppu = Ppu::default();
+        ppu.write_to_scroll(0b1011_0101);
+
+        assert_eq!(ppu.read_open_bus(), 0b1011_0101);
+        assert_eq!(ppu.read_status(), 0b0001_0101);
+    }
+
+    #[test]
+    fn palette_reads_fill_the_buffer_from_the_nametable_underneath() -> Result<()> {
+        let mut ppu = Ppu::default();
+        write_palette(&mut ppu, 0x2F05, 0x42)?;
+
+        read_palette(&mut ppu, 0x3F05)?;
+        assert_eq!(read_palette(&mut ppu, 0x2000)?, 0x42);
+
+        Ok(())
+    }
+
+    #[test]
+    fn switching_to_four_screen_allocates_cartridge_vram() -> Result<()> {
+        let mut ppu = Ppu::default();
+        ppu.set_mirroring(Mirroring::FourScreen);
+
+        write_palette(&mut ppu, 0x2800, 0x12)?;
+        write_palette(&mut ppu, 0x2C00, 0x34)?;
+        read_palette(&mut ppu, 0x2800)?;
+        assert_eq!(read_palette(&mut ppu, 0x2C00)?, 0x12);
+        assert_eq!(read_palette(&mut ppu, 0x2000)?, 0x34);
+
+        Ok(())
+    }
+
+    #[test]
+    fn no_register_access_sequence_panics() -> Result<()> {
+        let mut ppu = Ppu::new(rom![0; 16], Mirroring::FourScreen);
+
+        for addr in (0..=0xFFFF_u16).step_by(7) {
+            ppu.write_to_ppu_addr((addr >> 8) as u8);
+            ppu.write_to_ppu_addr(addr as u8);
+            ppu.write_to_data(addr as u8)?;
+            ppu.read_data()?;
+            ppu.write_to_ctrl(addr as u8);
+            ppu.write_to_mask(addr as u8);
+            ppu.write_to_oam_data(addr as u8);
+            ppu.tick(341);
+        }
+
+        Ok(())
+    }
+}
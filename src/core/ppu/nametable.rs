@@ -0,0 +1,189 @@
+use crate::{
+    core::Mirroring,
+    error::Result,
+    kb,
+    state::{invalid, stateful, StateReader, StateWriter, Stateful},
+};
+
+/// Nametable RAM four-screen boards carry on the cartridge
+pub const FOUR_SCREEN_VRAM_SIZE: usize = kb!(2);
+
+const PAGE_SIZE: usize = kb!(1);
+
+/// Memory backing one of the four 1K nametable pages at `$2000-$2FFF`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NametableSource {
+    /// One of the two 1K pages of the console's internal VRAM
+    Ciram(u8),
+    /// A 1K page of RAM on the cartridge, such as four-screen VRAM or MMC5 ExRAM
+    CartridgeVram(u8),
+    /// A 1K bank of CHR-ROM
+    CharacterRom(u16),
+}
+
+impl NametableSource {
+    /// Resolves a page of the nametable address space for a hardwired mirroring mode
+    pub fn from_mirroring(mirroring: Mirroring, page: usize) -> Self {
+        match (mirroring, page) {
+            (Mirroring::Vertical, page) => Self::Ciram(page as u8 & 1),
+            (Mirroring::Horizontal, page) => Self::Ciram(page as u8 >> 1),
+            (Mirroring::SingleScreenLower, _) => Self::Ciram(0),
+            (Mirroring::SingleScreenUpper, _) => Self::Ciram(1),
+            (Mirroring::FourScreen, 0 | 1) => Self::Ciram(page as u8),
+            (Mirroring::FourScreen, page) => Self::CartridgeVram(page as u8 - 2),
+        }
+    }
+}
+
+/// Per page nametable mapping. Pages follow the mirroring mode unless the mapper
+/// has overridden them.
+#[derive(Debug, Clone, Default)]
+pub struct Nametables {
+    overrides: [Option<NametableSource>; 4],
+    cartridge_vram: Vec<u8>,
+}
+
+impl Nametables {
+    pub fn new(mirroring: Mirroring) -> Self {
+        let cartridge_vram_size = match mirroring {
+            Mirroring::FourScreen => FOUR_SCREEN_VRAM_SIZE,
+            _ => 0,
+        };
+
+        Self {
+            overrides: [None; 4],
+            cartridge_vram: vec![0; cartridge_vram_size],
+        }
+    }
+
+    pub fn source(&self, mirroring: Mirroring, page: usize) -> NametableSource {
+        self.overrides[page & 0b11]
+            .unwrap_or_else(|| NametableSource::from_mirroring(mirroring, page & 0b11))
+    }
+
+    /// Maps a page to the given source, `None` restores the mirroring mode's mapping
+    pub fn map(&mut self, page: usize, source: Option<NametableSource>) {
+        self.overrides[page & 0b11] = source;
+    }
+
+    pub fn resize_cartridge_vram(&mut self, size: usize) {
+        self.cartridge_vram.resize(size, 0);
+    }
+
+    pub fn cartridge_vram(&self) -> &[u8] {
+        &self.cartridge_vram
+    }
+
+    pub fn cartridge_vram_mut(&mut self) -> &mut [u8] {
+        &mut self.cartridge_vram
+    }
+
+    pub fn read_cartridge_vram(&self, page: u8, offset: usize) -> u8 {
+        self.cartridge_vram
+            .get(page as usize * PAGE_SIZE + offset)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn write_cartridge_vram(&mut self, page: u8, offset: usize, value: u8) {
+        if let Some(byte) = self
+            .cartridge_vram
+            .get_mut(page as usize * PAGE_SIZE + offset)
+        {
+            *byte = value;
+        }
+    }
+}
+
+impl Default for NametableSource {
+    fn default() -> Self {
+        Self::Ciram(0)
+    }
+}
+
+impl Stateful for NametableSource {
+    fn save_state(&self, writer: &mut StateWriter) {
+        match self {
+            Self::Ciram(page) => {
+                writer.write_u8(0);
+                writer.write_u16(*page as u16);
+            }
+            Self::CartridgeVram(page) => {
+                writer.write_u8(1);
+                writer.write_u16(*page as u16);
+            }
+            Self::CharacterRom(bank) => {
+                writer.write_u8(2);
+                writer.write_u16(*bank);
+            }
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        let kind = reader.read_u8()?;
+        let page = reader.read_u16()?;
+
+        *self = match kind {
+            0 => Self::Ciram(page as u8),
+            1 => Self::CartridgeVram(page as u8),
+            2 => Self::CharacterRom(page),
+            value => return Err(invalid("nametable source", value)),
+        };
+
+        Ok(())
+    }
+}
+
+stateful!(Nametables {
+    overrides,
+    cartridge_vram
+});
+
+#[cfg(test)]
+mod tests {
+    use super::{NametableSource, Nametables};
+    use crate::core::Mirroring;
+
+    #[test]
+    fn mirroring_modes_select_the_expected_pages() {
+        let nametables = Nametables::default();
+        let pages = |mirroring| {
+            (0..4)
+                .map(|page| nametables.source(mirroring, page))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(pages(Mirroring::Vertical)
+            .into_iter()
+            .eq([0, 1, 0, 1].map(NametableSource::Ciram)));
+        assert!(pages(Mirroring::Horizontal)
+            .into_iter()
+            .eq([0, 0, 1, 1].map(NametableSource::Ciram)));
+        assert!(pages(Mirroring::SingleScreenUpper)
+            .into_iter()
+            .eq([1; 4].map(NametableSource::Ciram)));
+        assert!(pages(Mirroring::FourScreen).into_iter().eq([
+            NametableSource::Ciram(0),
+            NametableSource::Ciram(1),
+            NametableSource::CartridgeVram(0),
+            NametableSource::CartridgeVram(1),
+        ]));
+    }
+
+    #[test]
+    fn mapper_overrides_take_precedence_over_mirroring() {
+        let mut nametables = Nametables::default();
+        nametables.map(3, Some(NametableSource::CharacterRom(0x42)));
+
+        assert_eq!(
+            nametables.source(Mirroring::Vertical, 3),
+            NametableSource::CharacterRom(0x42)
+        );
+
+        nametables.map(3, None);
+        assert_eq!(
+            nametables.source(Mirroring::Vertical, 3),
+            NametableSource::Ciram(1)
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use crate::state::stateful;
+
+/// Roughly 600ms worth of PPU dots at the NTSC dot rate of 5.369318MHz
+pub const DECAY_DOTS: usize = 3_221_591;
+
+/// The PPU's I/O data-bus latch. Whatever was last driven onto the bus lingers in
+/// it until each bit individually decays back to 0.
+#[derive(Debug, Default, Clone)]
+pub struct OpenBus {
+    value: u8,
+    refreshed_at: [usize; 8],
+}
+
+impl OpenBus {
+    /// Drives the bits selected by `mask` onto the latch, refreshing their decay timers
+    pub fn refresh(&mut self, value: u8, mask: u8, now: usize) {
+        self.value = (self.value & !mask) | (value & mask);
+
+        (0..8)
+            .filter(|bit| mask & (1 << bit) != 0)
+            .for_each(|bit| self.refreshed_at[bit] = now);
+    }
+
+    pub fn read(&mut self, now: usize) -> u8 {
+        (0..8)
+            .filter(|&bit| now.saturating_sub(self.refreshed_at[bit]) >= DECAY_DOTS)
+            .for_each(|bit| self.value &= !(1 << bit));
+
+        self.value
+    }
+}
+
+stateful!(OpenBus {
+    value,
+    refreshed_at
+});
+
+#[cfg(test)]
+mod tests {
+    use super::{OpenBus, DECAY_DOTS};
+
+    #[test]
+    fn bits_decay_independently() {
+        let mut open_bus = OpenBus::default();
+        open_bus.refresh(0xFF, 0xFF, 0);
+        open_bus.refresh(0x00, 0x0F, DECAY_DOTS / 2);
+        open_bus.refresh(0x01, 0x01, DECAY_DOTS);
+
+        assert_eq!(open_bus.read(DECAY_DOTS - 1), 0xF1);
+        assert_eq!(open_bus.read(DECAY_DOTS), 0x01);
+        assert_eq!(open_bus.read(DECAY_DOTS * 2), 0x00);
+    }
+}
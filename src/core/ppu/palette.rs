@@ -0,0 +1,218 @@
+use super::Frame;
+use crate::error::{Error, Result};
+use std::{path::Path, str::FromStr};
+
+/// Number of colors the PPU can output, without emphasis applied
+pub const PALETTE_COLORS: usize = 64;
+/// Number of colors including every combination of the three emphasis bits
+pub const PALETTE_COLORS_WITH_EMPHASIS: usize = PALETTE_COLORS * 8;
+
+/// Attenuation applied to a channel when another channel is emphasised on a composite PPU
+const EMPHASIS_ATTENUATION: f32 = 0.816;
+
+const NTSC_2C02: [u32; PALETTE_COLORS] = [
+    0x7C7C7C, 0x0000FC, 0x0000BC, 0x4428BC, 0x940084, 0xA80020, 0xA81000, 0x881400, //
+    0x503000, 0x007800, 0x006800, 0x005800, 0x004058, 0x000000, 0x000000, 0x000000, //
+    0xBCBCBC, 0x0078F8, 0x0058F8, 0x6844FC, 0xD800CC, 0xE40058, 0xF83800, 0xE45C10, //
+    0xAC7C00, 0x00B800, 0x00A800, 0x00A844, 0x008888, 0x000000, 0x000000, 0x000000, //
+    0xF8F8F8, 0x3CBCFC, 0x6888FC, 0x9878F8, 0xF878F8, 0xF85898, 0xF87858, 0xFCA044, //
+    0xF8B800, 0xB8F818, 0x58D854, 0x58F898, 0x00E8D8, 0x787878, 0x000000, 0x000000, //
+    0xFCFCFC, 0xA4E4FC, 0xB8B8F8, 0xD8B8F8, 0xF8B8F8, 0xF8A4C0, 0xF0D0B0, 0xFCE0A8, //
+    0xF8D878, 0xD8F878, 0xB8F8B8, 0xB8F8D8, 0x00FCFC, 0xF8D8F8, 0x000000, 0x000000, //
+];
+
+/// The RGB PPUs output 3 bits per channel, stored here as octal `0oRGB` triples
+const RGB_2C03: [u16; PALETTE_COLORS] = [
+    0o333, 0o014, 0o006, 0o326, 0o403, 0o503, 0o510, 0o420, //
+    0o320, 0o120, 0o031, 0o040, 0o022, 0o000, 0o000, 0o000, //
+    0o555, 0o036, 0o027, 0o407, 0o507, 0o704, 0o700, 0o630, //
+    0o430, 0o140, 0o040, 0o053, 0o044, 0o000, 0o000, 0o000, //
+    0o777, 0o357, 0o447, 0o637, 0o707, 0o737, 0o740, 0o750, //
+    0o660, 0o360, 0o070, 0o276, 0o077, 0o000, 0o000, 0o000, //
+    0o777, 0o567, 0o657, 0o757, 0o747, 0o755, 0o764, 0o772, //
+    0o773, 0o572, 0o473, 0o276, 0o467, 0o000, 0o000, 0o000, //
+];
+
+/// Palettes shipped with the emulator
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BuiltinPalette {
+    /// Composite video output of the NTSC NES and Famicom
+    #[default]
+    Ntsc2C02,
+    /// RGB PPU found in Vs. System and PlayChoice-10 boards
+    Rgb2C03,
+    /// RGB PPU found in Vs. System boards, it shares its colors with the 2C03
+    Rgb2C05,
+}
+
+impl FromStr for BuiltinPalette {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "2c02" | "ntsc" | "default" => Ok(Self::Ntsc2C02),
+            "2c03" | "playchoice" => Ok(Self::Rgb2C03),
+            "2c05" | "vs" => Ok(Self::Rgb2C05),
+            _ => Err(Error::Unsupported(format!("unknown palette: {name}"))),
+        }
+    }
+}
+
+/// Maps NES color indices, including emphasis bits, to RGB
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::from(BuiltinPalette::default())
+    }
+}
+
+impl From<BuiltinPalette> for Palette {
+    fn from(palette: BuiltinPalette) -> Self {
+        match palette {
+            BuiltinPalette::Ntsc2C02 => {
+                let base = NTSC_2C02.map(|rgb| [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8]);
+
+                Self::with_attenuated_emphasis(&base)
+            }
+            BuiltinPalette::Rgb2C03 | BuiltinPalette::Rgb2C05 => {
+                let scale = |level: u16| ((level & 0o7) * 255 / 7) as u8;
+                let base = RGB_2C03.map(|rgb| [scale(rgb >> 6), scale(rgb >> 3), scale(rgb)]);
+
+                Self::with_saturated_emphasis(&base)
+            }
+        }
+    }
+}
+
+impl Palette {
+    /// Parses a `.pal` file, either 64 colors or 512 colors with every emphasis variant
+    pub fn new(data: &[u8]) -> Result<Self> {
+        let colors = data
+            .chunks_exact(3)
+            .map(|rgb| [rgb[0], rgb[1], rgb[2]])
+            .collect::<Vec<[u8; 3]>>();
+
+        match data.len() {
+            len if len == PALETTE_COLORS * 3 => {
+                let mut base = [[0; 3]; PALETTE_COLORS];
+                base.copy_from_slice(&colors);
+
+                Ok(Self::with_attenuated_emphasis(&base))
+            }
+            len if len == PALETTE_COLORS_WITH_EMPHASIS * 3 => Ok(Self { colors }),
+            len => Err(Error::Unsupported(format!(
+                "palette files must be 192 or 1536 bytes long, got {len}"
+            ))),
+        }
+    }
+
+    /// RGB value of a pixel as stored in a `Frame`
+    pub fn color(&self, pixel: u16) -> [u8; 3] {
+        self.colors[pixel as usize % PALETTE_COLORS_WITH_EMPHASIS]
+    }
+
+    /// Converts a frame into tightly packed RGBA bytes
+    pub fn to_rgba(&self, frame: &Frame) -> Vec<u8> {
+        frame
+            .as_ref()
+            .iter()
+            .flat_map(|&pixel| {
+                let [r, g, b] = self.color(pixel);
+                [r, g, b, 0xFF]
+            })
+            .collect()
+    }
+
+    /// Converts a frame into tightly packed RGB bytes
+    pub fn to_rgb(&self, frame: &Frame) -> Vec<u8> {
+        frame
+            .as_ref()
+            .iter()
+            .flat_map(|&pixel| self.color(pixel))
+            .collect()
+    }
+
+    /// Composite PPUs darken the channels that aren't emphasised
+    fn with_attenuated_emphasis(base: &[[u8; 3]; PALETTE_COLORS]) -> Self {
+        Self::with_emphasis(base, |emphasis, channel, value| {
+            let attenuation = (0..3)
+                .filter(|&other| other != channel && emphasis & (1 << other) != 0)
+                .fold(1.0, |acc, _| acc * EMPHASIS_ATTENUATION);
+
+            (value as f32 * attenuation) as u8
+        })
+    }
+
+    /// RGB PPUs drive emphasised channels at full intensity
+    fn with_saturated_emphasis(base: &[[u8; 3]; PALETTE_COLORS]) -> Self {
+        Self::with_emphasis(base, |emphasis, channel, value| {
+            match emphasis & (1 << channel) != 0 {
+                true => 0xFF,
+                false => value,
+            }
+        })
+    }
+
+    fn with_emphasis<F>(base: &[[u8; 3]; PALETTE_COLORS], apply: F) -> Self
+    where
+        F: Fn(usize, usize, u8) -> u8,
+    {
+        let colors = (0..PALETTE_COLORS_WITH_EMPHASIS)
+            .map(|index| {
+                let (emphasis, color) = (index / PALETTE_COLORS, index % PALETTE_COLORS);
+                let rgb = base[color];
+
+                // columns $xE/$xF are forced black and unaffected by emphasis
+                match color & 0x0E == 0x0E {
+                    true => rgb,
+                    false => [0, 1, 2].map(|channel| apply(emphasis, channel, rgb[channel])),
+                }
+            })
+            .collect();
+
+        Self { colors }
+    }
+}
+
+impl TryFrom<&Path> for Palette {
+    type Error = crate::error::Error;
+
+    fn try_from(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path)?;
+
+        Self::new(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BuiltinPalette, Palette, PALETTE_COLORS};
+
+    #[test]
+    fn short_palette_files_are_expanded_with_emphasis() {
+        let data = (0..PALETTE_COLORS * 3).map(|_| 200).collect::<Vec<u8>>();
+        let palette = Palette::new(&data).unwrap();
+
+        assert_eq!(palette.color(0x01), [200, 200, 200]);
+        // red emphasis darkens green and blue
+        assert_eq!(palette.color(0b001 << 6 | 0x01), [200, 163, 163]);
+    }
+
+    #[test]
+    fn palette_files_of_unexpected_size_are_rejected() {
+        assert!(Palette::new(&[0; 100]).is_err());
+        assert!(Palette::new(&[0; 1536]).is_ok());
+    }
+
+    #[test]
+    fn rgb_ppu_emphasis_saturates_channels() {
+        let palette = Palette::from(BuiltinPalette::Rgb2C03);
+
+        assert_eq!(palette.color(0x0D), [0, 0, 0]);
+        assert_eq!(palette.color(0b100 << 6 | 0x10), [182, 182, 255]);
+    }
+}
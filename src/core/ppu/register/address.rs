@@ -1,3 +1,5 @@
+use crate::state::stateful;
+
 #[derive(Debug)]
 pub struct AddressRegister {
     value: (u8, u8),
@@ -21,7 +23,7 @@ impl AddressRegister {
         };
 
         if self.get() > 0x3FFF {
-            self.set(self.get());
+            self.set(self.get() & 0x3FFF);
         }
 
         self.latch = !self.latch;
@@ -35,7 +37,7 @@ impl AddressRegister {
         };
 
         if self.get() > 0x3FFF {
-            self.set(self.get())
+            self.set(self.get() & 0x3FFF)
         }
     }
 
@@ -52,3 +54,5 @@ impl AddressRegister {
         self.value.1 = (data & 0xFF) as u8;
     }
 }
+
+stateful!(AddressRegister { value, latch });
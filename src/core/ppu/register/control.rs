@@ -36,6 +36,10 @@ impl ControlRegister {
         }
     }
 
+    pub fn nametable_index(&self) -> u8 {
+        self.bits() & 0b11
+    }
+
     pub fn vram_address_increment(&self) -> u8 {
         match self.contains(ControlRegister::VRAM_ADD_INCREMENT) {
             true => 32,
@@ -45,6 +49,20 @@ impl ControlRegister {
 
     pub fn sprite_pattern_address(&self) -> u16 {
         match self.contains(ControlRegister::SPRITE_PATTERN_ADDR) {
+            true => 0x1000,
+            false => 0x0000,
+        }
+    }
+
+    pub fn background_pattern_address(&self) -> u16 {
+        match self.contains(ControlRegister::BACKROUND_PATTERN_ADDR) {
+            true => 0x1000,
+            false => 0x0000,
+        }
+    }
+
+    pub fn sprite_size(&self) -> u8 {
+        match self.contains(ControlRegister::SPRITE_SIZE) {
             true => 16,
             false => 8,
         }
@@ -48,6 +48,11 @@ impl MaskRegister {
         self.contains(MaskRegister::SHOW_SPRITES)
     }
 
+    /// The red, green and blue emphasis bits shifted down to `0b0000_0BGR`
+    pub fn emphasis_bits(&self) -> u8 {
+        self.bits() >> 5
+    }
+
     pub fn emphasise(&self) -> Vec<Color> {
         let mut result = Vec::<Color>::new();
         if self.contains(MaskRegister::EMPHASISE_RED) {
@@ -4,6 +4,7 @@ mod mask;
 mod scroll;
 mod status;
 
+use crate::state::{stateful, stateful_bitflags};
 use address::AddressRegister;
 use control::ControlRegister;
 use mask::MaskRegister;
@@ -18,3 +19,13 @@ pub struct PpuRegisters {
     pub scroll: ScrollRegister,
     pub status: StatusRegister,
 }
+
+stateful_bitflags!(ControlRegister, MaskRegister, StatusRegister);
+
+stateful!(PpuRegisters {
+    address,
+    control,
+    mask,
+    scroll,
+    status,
+});
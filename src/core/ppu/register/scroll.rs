@@ -1,3 +1,5 @@
+use crate::state::stateful;
+
 #[derive(Debug, Default)]
 pub struct ScrollRegister {
     x: u8,
@@ -15,7 +17,17 @@ impl ScrollRegister {
         self.latch = !self.latch;
     }
 
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    pub fn y(&self) -> u8 {
+        self.y
+    }
+
     pub fn reset_latch(&mut self) {
         self.latch = false;
     }
 }
+
+stateful!(ScrollRegister { x, y, latch });
@@ -0,0 +1,312 @@
+use super::{frame::FRAME_WIDTH, Ppu};
+
+const MAX_SPRITES_PER_SCANLINE: usize = 8;
+
+/// A sprite pixel that won the priority evaluation for a column
+#[derive(Clone, Copy)]
+struct SpritePixel {
+    palette_index: u8,
+    behind_background: bool,
+    is_sprite_zero: bool,
+}
+
+impl Ppu {
+    /// Renders a single visible scanline into the frame
+    pub(super) fn render_scanline(&mut self, y: usize) {
+        let mask = &self.registers.mask;
+        if !mask.show_background() && !mask.show_sprites() {
+            let backdrop = self.output_color(0);
+            self.frame.scanline_mut(y).fill(backdrop);
+
+            return;
+        }
+
+        let background = self.background_scanline(y);
+        let sprites = self.sprite_scanline(y);
+        let show_background = self.registers.mask.show_background();
+        let show_sprites = self.registers.mask.show_sprites();
+
+        for x in 0..FRAME_WIDTH {
+            let background_index = background[x];
+            let background_opaque = background_index & 0b11 != 0;
+
+            let palette_index = match sprites[x] {
+                Some(sprite) => {
+                    if sprite.is_sprite_zero
+                        && background_opaque
+                        && show_background
+                        && show_sprites
+                        && x != FRAME_WIDTH - 1
+                    {
+                        self.registers.status.set_sprite_zero_hit(true);
+                    }
+
+                    match sprite.behind_background && background_opaque {
+                        true => background_index,
+                        false => sprite.palette_index,
+                    }
+                }
+                None => background_index,
+            };
+
+            let color = self.output_color(palette_index);
+            self.frame.set_pixel(x, y, color);
+        }
+    }
+
+    /// Applies greyscale and color emphasis from the mask register to a palette entry
+    pub(super) fn output_color(&self, palette_index: u8) -> u16 {
+        let mask = &self.registers.mask;
+        let mut color = self.palette_table[Self::palette_address(palette_index as u16)];
+        if mask.is_grayscale() {
+            color &= 0x30;
+        }
+
+        let mut emphasis = mask.emphasis_bits();
+        if self.region.swaps_red_green_emphasis() {
+            emphasis = (emphasis & 0b100) | (emphasis & 0b01) << 1 | (emphasis & 0b10) >> 1;
+        }
+
+        (color as u16 & 0x3F) | (emphasis as u16) << 6
+    }
+
+    fn background_scanline(&self, y: usize) -> [u8; FRAME_WIDTH] {
+        let mut line = [0; FRAME_WIDTH];
+        if !self.registers.mask.show_background() {
+            return line;
+        }
+
+        let nametable = self.registers.control.nametable_index() as usize;
+        let scroll_x = self.registers.scroll.x() as usize + (nametable & 1) * 256;
+        let scroll_y = self.registers.scroll.y() as usize + (nametable >> 1) * 240;
+        let pattern_base = self.registers.control.background_pattern_address();
+
+        let full_y = (y + scroll_y) % 480;
+        let (nametable_y, local_y) = (full_y / 240, full_y % 240);
+
+        for (x, pixel) in line.iter_mut().enumerate() {
+            if x < 8 && !self.registers.mask.leftmost_8pxl_background() {
+                continue;
+            }
+
+            let full_x = (x + scroll_x) % 512;
+            let (nametable_x, local_x) = (full_x / 256, full_x % 256);
+            let base = 0x2000 + ((nametable_y * 2 + nametable_x) * 0x400) as u16;
+
+            let tile_offset = ((local_y / 8) * 32 + local_x / 8) as u16;
+            let tile = self.read_nametable(base + tile_offset) as u16;
+
+            let attribute_offset = 0x3C0 + ((local_y / 32) * 8 + local_x / 32) as u16;
+            let attribute = self.read_nametable(base + attribute_offset);
+            let shift = ((local_y % 32) / 16) * 4 + ((local_x % 32) / 16) * 2;
+            let palette = (attribute >> shift) & 0b11;
+
+            let row = pattern_base + tile * 16 + (local_y % 8) as u16;
+            let bit = 7 - (local_x % 8);
+            let value = self.pattern_pixel(row, bit);
+
+            if value != 0 {
+                *pixel = palette * 4 + value;
+            }
+        }
+
+        line
+    }
+
+    fn sprite_scanline(&mut self, y: usize) -> [Option<SpritePixel>; FRAME_WIDTH] {
+        let mut line = [None; FRAME_WIDTH];
+        if !self.registers.mask.show_sprites() {
+            return line;
+        }
+
+        let height = self.registers.control.sprite_size() as usize;
+        let visible = (0..64)
+            .filter(|sprite| {
+                let top = self.oam_data[sprite * 4] as usize + 1;
+                (top..top + height).contains(&y)
+            })
+            .collect::<Vec<usize>>();
+
+        if visible.len() > MAX_SPRITES_PER_SCANLINE {
+            self.registers.status.set_sprite_overflow(true);
+        }
+
+        for &sprite in visible.iter().take(MAX_SPRITES_PER_SCANLINE) {
+            let entry = &self.oam_data[sprite * 4..sprite * 4 + 4];
+            let (top, tile, attributes, left) = (
+                entry[0] as usize + 1,
+                entry[1] as u16,
+                entry[2],
+                entry[3] as usize,
+            );
+
+            let flip_horizontal = attributes & 0b0100_0000 != 0;
+            let flip_vertical = attributes & 0b1000_0000 != 0;
+            let mut row = y - top;
+            if flip_vertical {
+                row = height - 1 - row;
+            }
+
+            let row_address = match height {
+                16 => {
+                    let table = (tile & 1) * 0x1000;
+                    let tile = (tile & 0xFE) + (row / 8) as u16;
+
+                    table + tile * 16 + (row % 8) as u16
+                }
+                _ => {
+                    let table = self.registers.control.sprite_pattern_address();
+
+                    table + tile * 16 + row as u16
+                }
+            };
+
+            for column in 0..8 {
+                let x = left + column;
+                if x >= FRAME_WIDTH || line[x].is_some() {
+                    continue;
+                }
+
+                if x < 8 && !self.registers.mask.leftmost_8pxl_sprite() {
+                    continue;
+                }
+
+                let bit = match flip_horizontal {
+                    true => column,
+                    false => 7 - column,
+                };
+
+                let value = self.pattern_pixel(row_address, bit);
+                if value != 0 {
+                    line[x] = Some(SpritePixel {
+                        palette_index: 0x10 + (attributes & 0b11) * 4 + value,
+                        behind_background: attributes & 0b0010_0000 != 0,
+                        is_sprite_zero: sprite == 0,
+                    });
+                }
+            }
+        }
+
+        line
+    }
+
+    /// Reads the 2-bit value of a pixel from a pattern table row
+    fn pattern_pixel(&self, row_address: u16, bit: usize) -> u8 {
+        let low = self.read_pattern(row_address);
+        let high = self.read_pattern(row_address + 8);
+
+        ((low >> bit) & 1) | (((high >> bit) & 1) << 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Ppu, DOTS_PER_SCANLINE};
+    use crate::{
+        core::{Mirroring, Rom},
+        error::Result,
+    };
+
+    const SPRITE_ZERO_HIT: u8 = 0b0100_0000;
+    const SPRITE_OVERFLOW: u8 = 0b0010_0000;
+    const BACKDROP: u16 = 0x0F;
+    const BACKGROUND: u16 = 0x21;
+    const SPRITE: u16 = 0x16;
+
+    /// Vertically mirrored PPU whose tile 1 is solid color 1, showing the background
+    /// and sprites everywhere
+    fn ppu() -> Result<Ppu> {
+        let mut character_rom = vec![0; 0x2000];
+        character_rom[16..24].fill(0xFF);
+        let mut ppu = Ppu::new(Rom::new(character_rom), Mirroring::Vertical);
+
+        write(&mut ppu, 0x3F00, BACKDROP as u8)?;
+        write(&mut ppu, 0x3F01, BACKGROUND as u8)?;
+        write(&mut ppu, 0x3F11, SPRITE as u8)?;
+        ppu.write_to_mask(0b0001_1110);
+
+        Ok(ppu)
+    }
+
+    fn write(ppu: &mut Ppu, addr: u16, value: u8) -> Result<()> {
+        ppu.write_to_ppu_addr((addr >> 8) as u8);
+        ppu.write_to_ppu_addr(addr as u8);
+        ppu.write_to_data(value)
+    }
+
+    /// Places `sprite` with its top row on scanline `y`
+    fn place_sprite(ppu: &mut Ppu, sprite: u8, y: u8, x: u8) {
+        ppu.write_to_oam_addr(sprite * 4);
+        for byte in [y - 1, 1, 0, x] {
+            ppu.write_to_oam_data(byte);
+        }
+    }
+
+    #[test]
+    fn sprite_zero_hit_is_set_on_the_first_overlapping_scanline() -> Result<()> {
+        let mut ppu = ppu()?;
+        // background tile covering x 40-47, y 40-47
+        write(&mut ppu, 0x2000 + 5 * 32 + 5, 1)?;
+        place_sprite(&mut ppu, 0, 45, 40);
+
+        ppu.tick(DOTS_PER_SCANLINE * 45);
+        assert_eq!(ppu.read_status() & SPRITE_ZERO_HIT, 0);
+
+        ppu.tick(DOTS_PER_SCANLINE);
+        assert_ne!(ppu.read_status() & SPRITE_ZERO_HIT, 0);
+
+        // cleared once vertical blank starts
+        ppu.tick(DOTS_PER_SCANLINE * (241 - 46));
+        assert_eq!(ppu.read_status() & SPRITE_ZERO_HIT, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn only_eight_sprites_are_drawn_per_scanline() -> Result<()> {
+        let mut ppu = ppu()?;
+        for sprite in 0..8 {
+            place_sprite(&mut ppu, sprite, 100, sprite * 10);
+        }
+
+        ppu.render_scanline(100);
+        assert_eq!(ppu.read_status() & SPRITE_OVERFLOW, 0);
+
+        place_sprite(&mut ppu, 8, 100, 80);
+        ppu.render_scanline(100);
+        assert_ne!(ppu.read_status() & SPRITE_OVERFLOW, 0);
+
+        assert_eq!(ppu.frame().get_pixel(70, 100), SPRITE);
+        assert_eq!(ppu.frame().get_pixel(80, 100), BACKDROP);
+
+        Ok(())
+    }
+
+    #[test]
+    fn scrolling_wraps_around_the_nametables() -> Result<()> {
+        let mut ppu = ppu()?;
+        write(&mut ppu, 0x2000, 1)?;
+
+        // starting in the right nametable, 4 pixels before its end
+        ppu.write_to_ctrl(0b01);
+        ppu.read_status();
+        ppu.write_to_scroll(252);
+        ppu.write_to_scroll(0);
+        ppu.render_scanline(0);
+        let pixels: Vec<u16> = (0..16).map(|x| ppu.frame().get_pixel(x, 0)).collect();
+        assert_eq!(pixels[..4], [BACKDROP; 4]);
+        assert_eq!(pixels[4..12], [BACKGROUND; 8]);
+        assert_eq!(pixels[12..], [BACKDROP; 4]);
+
+        // 4 rows above the bottom of the top nametables, the bottom ones mirror them
+        ppu.write_to_ctrl(0);
+        ppu.write_to_scroll(0);
+        ppu.write_to_scroll(236);
+        ppu.render_scanline(3);
+        ppu.render_scanline(4);
+        assert_eq!(ppu.frame().get_pixel(0, 3), BACKDROP);
+        assert_eq!(ppu.frame().get_pixel(0, 4), BACKGROUND);
+
+        Ok(())
+    }
+}
@@ -2,6 +2,7 @@ use crate::{
     error::Result,
     io::{Read, Write},
     kb,
+    state::{StateReader, StateWriter, Stateful},
 };
 use std::fmt::Display;
 
@@ -57,3 +58,15 @@ impl Display for Ram {
         )
     }
 }
+
+impl Stateful for Ram {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.0);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.0.copy_from_slice(reader.read_bytes(RAM_SIZE)?);
+
+        Ok(())
+    }
+}
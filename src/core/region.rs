@@ -0,0 +1,164 @@
+use super::ppu::DOTS_PER_SCANLINE;
+use crate::{
+    error::{Error, Result},
+    state::{invalid, StateReader, StateWriter, Stateful},
+};
+use std::str::FromStr;
+
+const NTSC_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const PAL_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+const NTSC_DMC_RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const PAL_DMC_RATES: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+/// CPU cycles at which the frame counter clocks its units, the 4-step sequence ends
+/// on the fourth step while the 5-step sequence carries on to the fifth
+const NTSC_FRAME_COUNTER_STEPS: [usize; 5] = [7457, 14913, 22371, 29829, 37281];
+const PAL_FRAME_COUNTER_STEPS: [usize; 5] = [8313, 16627, 24939, 33253, 41565];
+
+/// Console timing, picked from the cartridge header unless overridden
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    /// Famiclone timing, a PAL-like frame with NTSC style PPU and APU behaviour
+    Dendy,
+}
+
+impl Region {
+    /// Maps the NES 2.0 CPU/PPU timing field, multi-region carts default to NTSC
+    pub fn from_nes2_timing(timing: u8) -> Self {
+        match timing & 0b11 {
+            1 => Self::Pal,
+            3 => Self::Dendy,
+            _ => Self::Ntsc,
+        }
+    }
+
+    pub fn cpu_clock_rate(&self) -> f64 {
+        match self {
+            Self::Ntsc => 1_789_773.0,
+            Self::Pal => 1_662_607.0,
+            Self::Dendy => 1_773_448.0,
+        }
+    }
+
+    /// Frames per second, from the PPU clock and the dots in a frame
+    pub fn frame_rate(&self) -> f64 {
+        let (numerator, denominator) = self.ppu_dots_per_cpu_cycle();
+        let ppu_clock_rate = self.cpu_clock_rate() * numerator as f64 / denominator as f64;
+
+        let mut dots = (self.scanlines() as usize * DOTS_PER_SCANLINE) as f64;
+        if self.skips_odd_frame_dot() {
+            // every other frame is a dot shorter
+            dots -= 0.5;
+        }
+
+        ppu_clock_rate / dots
+    }
+
+    pub fn scanlines(&self) -> u16 {
+        match self {
+            Self::Ntsc => 262,
+            Self::Pal | Self::Dendy => 312,
+        }
+    }
+
+    pub fn vblank_scanline(&self) -> u16 {
+        match self {
+            Self::Ntsc | Self::Pal => 241,
+            Self::Dendy => 291,
+        }
+    }
+
+    /// PPU dots per CPU cycle as a `(numerator, denominator)` pair, 3.2 on PAL
+    pub fn ppu_dots_per_cpu_cycle(&self) -> (usize, usize) {
+        match self {
+            Self::Ntsc | Self::Dendy => (3, 1),
+            Self::Pal => (16, 5),
+        }
+    }
+
+    /// Whether the pre-render scanline is a dot shorter on odd frames while rendering
+    pub fn skips_odd_frame_dot(&self) -> bool {
+        matches!(self, Self::Ntsc)
+    }
+
+    /// The PAL PPU swaps the red and green emphasis bits
+    pub fn swaps_red_green_emphasis(&self) -> bool {
+        matches!(self, Self::Pal | Self::Dendy)
+    }
+
+    pub fn frame_counter_steps(&self) -> [usize; 5] {
+        match self {
+            Self::Ntsc | Self::Dendy => NTSC_FRAME_COUNTER_STEPS,
+            Self::Pal => PAL_FRAME_COUNTER_STEPS,
+        }
+    }
+
+    pub fn noise_periods(&self) -> &'static [u16; 16] {
+        match self {
+            Self::Ntsc | Self::Dendy => &NTSC_NOISE_PERIODS,
+            Self::Pal => &PAL_NOISE_PERIODS,
+        }
+    }
+
+    pub fn dmc_rates(&self) -> &'static [u16; 16] {
+        match self {
+            Self::Ntsc | Self::Dendy => &NTSC_DMC_RATES,
+            Self::Pal => &PAL_DMC_RATES,
+        }
+    }
+}
+
+impl FromStr for Region {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "ntsc" => Ok(Self::Ntsc),
+            "pal" => Ok(Self::Pal),
+            "dendy" => Ok(Self::Dendy),
+            _ => Err(Error::Unsupported(format!("unknown region: {name}"))),
+        }
+    }
+}
+
+impl Stateful for Region {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(*self as u8);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        *self = match reader.read_u8()? {
+            0 => Self::Ntsc,
+            1 => Self::Pal,
+            2 => Self::Dendy,
+            value => return Err(invalid("region", value)),
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Region;
+
+    #[test]
+    fn frame_rates_follow_from_the_ppu_clock() {
+        assert!((Region::Ntsc.frame_rate() - 60.0988).abs() < 1e-4);
+        assert!((Region::Pal.frame_rate() - 50.007).abs() < 1e-3);
+        // a PAL sized frame at the same PPU clock rate as PAL
+        assert!((Region::Dendy.frame_rate() - Region::Pal.frame_rate()).abs() < 1e-4);
+    }
+}
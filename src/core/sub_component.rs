@@ -4,7 +4,9 @@ use num::{
 };
 use std::ops::{Add, AddAssign, Sub, SubAssign};
 
-#[derive(Debug, Default)]
+use crate::state::{StateReader, StateWriter, Stateful};
+
+#[derive(Debug, Default, Clone)]
 #[repr(transparent)]
 pub struct SubComponent<T>(T);
 
@@ -116,6 +118,16 @@ macro_rules! impl_min_max {
 
 impl_min_max![usize, u8, u16];
 
+impl<T: Stateful> Stateful for SubComponent<T> {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.0.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> crate::error::Result<()> {
+        self.0.load_state(reader)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::SubComponent;
@@ -0,0 +1,132 @@
+use std::fmt::Display;
+
+use crate::core::{
+    opcode::{OpCode, OPCODE_MAP},
+    AddressingMode,
+};
+
+/// JMP (indirect), the only 3-byte instruction without a plain absolute operand
+const JMP_INDIRECT: u8 = 0x6C;
+
+/// A decoded instruction, or a data byte that isn't a known opcode
+#[derive(Debug, Clone)]
+pub struct Instruction {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub opcode: Option<&'static OpCode>,
+}
+
+impl Instruction {
+    /// The operand in assembler syntax, relative branches show their target
+    pub fn operand(&self) -> String {
+        let Some(opcode) = self.opcode else {
+            return format!("${:02x}", self.bytes[0]);
+        };
+        let byte = || self.bytes[1];
+        let word = || u16::from_le_bytes([self.bytes[1], self.bytes[2]]);
+
+        match (opcode.mode(), self.bytes.len()) {
+            (AddressingMode::Immediate, _) => format!("#${:02x}", byte()),
+            (AddressingMode::ZeroPage, _) => format!("${:02x}", byte()),
+            (AddressingMode::ZeroPageX, _) => format!("${:02x},X", byte()),
+            (AddressingMode::ZeroPageY, _) => format!("${:02x},Y", byte()),
+            (AddressingMode::Absolute, _) => format!("${:04x}", word()),
+            (AddressingMode::AbsoluteX, _) => format!("${:04x},X", word()),
+            (AddressingMode::AbsoluteY, _) => format!("${:04x},Y", word()),
+            (AddressingMode::IndirectX, _) => format!("(${:02x},X)", byte()),
+            (AddressingMode::IndirectY, _) => format!("(${:02x}),Y", byte()),
+            (AddressingMode::NoneAddressing, 2) => {
+                let target = self
+                    .address
+                    .wrapping_add(2)
+                    .wrapping_add(byte() as i8 as u16);
+                format!("${target:04x}")
+            }
+            (AddressingMode::NoneAddressing, 3) if opcode.code() == JMP_INDIRECT => {
+                format!("(${:04x})", word())
+            }
+            (AddressingMode::NoneAddressing, 3) => format!("${:04x}", word()),
+            (AddressingMode::NoneAddressing, _) => match opcode.code() {
+                // accumulator shifts and rotates
+                0x0A | 0x4A | 0x2A | 0x6A => "A".to_owned(),
+                _ => String::new(),
+            },
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hex = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mnemonic = self.opcode.map_or(".db", |opcode| opcode.mnemonic());
+
+        write!(
+            f,
+            "{}",
+            format!(
+                "{:04x}  {hex:8} {mnemonic: >4} {}",
+                self.address,
+                self.operand()
+            )
+            .trim_end()
+        )
+    }
+}
+
+/// Decodes `program` as if it were mapped at `origin`. Bytes that aren't opcodes,
+/// and instructions cut off by the end of `program`, come out as data.
+pub fn disassemble(program: &[u8], origin: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < program.len() {
+        let address = origin.wrapping_add(offset as u16);
+        let opcode = OPCODE_MAP
+            .get(&program[offset])
+            .copied()
+            .filter(|opcode| offset + opcode.len() as usize <= program.len());
+        let len = opcode.map_or(1, |opcode| opcode.len() as usize);
+
+        instructions.push(Instruction {
+            address,
+            bytes: program[offset..offset + len].to_vec(),
+            opcode,
+        });
+        offset += len;
+    }
+
+    instructions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::disassemble;
+
+    #[test]
+    fn instructions_are_decoded_in_assembler_syntax() {
+        let program = [
+            0xA9, 0x01, 0x8D, 0x16, 0x40, 0xD0, 0xFB, 0x6C, 0x34, 0x12, 0x0A, 0x02, 0x4C,
+        ];
+        let lines: Vec<String> = disassemble(&program, 0x8000)
+            .iter()
+            .map(|instruction| instruction.to_string())
+            .collect();
+
+        assert_eq!(
+            lines,
+            [
+                "8000  a9 01     LDA #$01",
+                "8002  8d 16 40  STA $4016",
+                "8005  d0 fb     BNE $8002",
+                "8007  6c 34 12  JMP ($1234)",
+                "800a  0a        ASL A",
+                "800b  02        .db $02",
+                "800c  4c        .db $4c",
+            ]
+        );
+    }
+}
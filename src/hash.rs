@@ -0,0 +1,176 @@
+const CRC32_POLYNOMIAL: u32 = 0xEDB8_8320;
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ CRC32_POLYNOMIAL,
+                _ => crc >> 1,
+            };
+            bit += 1;
+        }
+        table[index] = crc;
+        index += 1;
+    }
+    table
+};
+
+/// CRC-32 as used by zip and the NES 2.0 ROM databases
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+const MD5_SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9,
+    14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15,
+    21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+/// Integer part of `abs(sin(i + 1)) * 2^32`
+const MD5_CONSTANTS: [u32; 64] = [
+    0xD76A_A478,
+    0xE8C7_B756,
+    0x2420_70DB,
+    0xC1BD_CEEE,
+    0xF57C_0FAF,
+    0x4787_C62A,
+    0xA830_4613,
+    0xFD46_9501,
+    0x6980_98D8,
+    0x8B44_F7AF,
+    0xFFFF_5BB1,
+    0x895C_D7BE,
+    0x6B90_1122,
+    0xFD98_7193,
+    0xA679_438E,
+    0x49B4_0821,
+    0xF61E_2562,
+    0xC040_B340,
+    0x265E_5A51,
+    0xE9B6_C7AA,
+    0xD62F_105D,
+    0x0244_1453,
+    0xD8A1_E681,
+    0xE7D3_FBC8,
+    0x21E1_CDE6,
+    0xC337_07D6,
+    0xF4D5_0D87,
+    0x455A_14ED,
+    0xA9E3_E905,
+    0xFCEF_A3F8,
+    0x676F_02D9,
+    0x8D2A_4C8A,
+    0xFFFA_3942,
+    0x8771_F681,
+    0x6D9D_6122,
+    0xFDE5_380C,
+    0xA4BE_EA44,
+    0x4BDE_CFA9,
+    0xF6BB_4B60,
+    0xBEBF_BC70,
+    0x289B_7EC6,
+    0xEAA1_27FA,
+    0xD4EF_3085,
+    0x0488_1D05,
+    0xD9D4_D039,
+    0xE6DB_99E5,
+    0x1FA2_7CF8,
+    0xC4AC_5665,
+    0xF429_2244,
+    0x432A_FF97,
+    0xAB94_23A7,
+    0xFC93_A039,
+    0x655B_59C3,
+    0x8F0C_CC92,
+    0xFFEF_F47D,
+    0x8584_5DD1,
+    0x6FA8_7E4F,
+    0xFE2C_E6E0,
+    0xA301_4314,
+    0x4E08_11A1,
+    0xF753_7E82,
+    0xBD3A_F235,
+    0x2AD7_D2BB,
+    0xEB86_D391,
+];
+
+/// MD5, which FCEUX movies use to identify the ROM they were recorded on
+pub fn md5(data: &[u8]) -> [u8; 16] {
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend(((data.len() as u64).wrapping_mul(8)).to_le_bytes());
+
+    let mut state: [u32; 4] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476];
+    for block in message.chunks_exact(64) {
+        let words: Vec<u32> = block
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect();
+
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(MD5_CONSTANTS[i])
+                .wrapping_add(words[g])
+                .rotate_left(MD5_SHIFTS[i]);
+            (a, b, c, d) = (d, b.wrapping_add(rotated), b, c);
+        }
+
+        for (value, add) in state.iter_mut().zip([a, b, c, d]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0; 16];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_le_bytes());
+    }
+
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, md5};
+
+    #[test]
+    fn crc32_matches_the_reference_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn md5_matches_the_reference_digests() {
+        let hex = |digest: [u8; 16]| {
+            digest
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<String>()
+        };
+
+        assert_eq!(hex(md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(hex(md5(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            hex(md5(b"The quick brown fox jumps over the lazy dog")),
+            "9e107d9d372bb6826bd81d3542a419d6"
+        );
+        assert_eq!(hex(md5(&[0x61; 1000])), "cabe45dcc9ae5b66ba86600cca6b8ba8");
+    }
+}
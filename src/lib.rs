@@ -1,11 +1,20 @@
 #![allow(dead_code)]
 
+pub mod capture;
 pub mod core;
+pub mod disasm;
 pub mod error;
+pub mod hash;
 pub mod io;
 mod macros;
+pub mod movie;
+mod nes;
+pub mod oracle;
+pub mod state;
+pub mod test_rom;
 mod trace;
 
+pub use nes::{Nes, NesBuilder};
 pub use trace::trace;
 
 #[macro_use]
@@ -16,6 +25,29 @@ extern crate lazy_static;
 
 #[cfg(test)]
 mod test {
+    use crate::{core::Cartridge, Nes};
+
+    /// NROM image with `program` at `$8000`, where the reset vector points, and a
+    /// blank 8K of CHR
+    pub(crate) fn nrom_cartridge(program: &[u8]) -> Cartridge {
+        let mut data = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut rom = vec![0; 0x4000];
+        rom[..program.len()].copy_from_slice(program);
+        rom[0x3FFC..].copy_from_slice(&[0x00, 0x80, 0x00, 0x80]);
+        data.extend(rom);
+        data.extend(vec![0; 0x2000]);
+
+        Cartridge::new(data).unwrap()
+    }
+
+    /// A console running `nrom_cartridge(program)`
+    pub(crate) fn nrom_with_program(program: &[u8]) -> Nes {
+        Nes::builder()
+            .cartridge(nrom_cartridge(program))
+            .build()
+            .unwrap()
+    }
+
     // use crate::core::Bus;
 
     // use super::{
@@ -0,0 +1,704 @@
+//! Input movies in FCEUX's text `.fm2` format.
+//!
+//! Only standard controllers, optionally through a Four Score, are supported. Besides
+//! the usual header, movies can carry a CRC-32 of the work RAM after every frame in
+//! `comment ramhash <frame> <hash>` lines, which FCEUX ignores, so playback notices
+//! the moment it desyncs.
+
+use std::{collections::BTreeMap, fmt::Write, path::Path};
+
+use crate::{
+    core::{
+        input::{Buttons, DeviceSetup, InputState, PLAYERS},
+        Region,
+    },
+    error::{Error, Result},
+    hash, Nes,
+};
+
+const FM2_VERSION: u32 = 3;
+/// Button columns of an input log field, from bit 7 down to bit 0 of `Buttons`
+const BUTTON_COLUMNS: usize = 8;
+const CHECKSUM_PREFIX: &str = "base64:";
+const RAM_HASH_COMMENT: &str = "ramhash ";
+/// `port0`/`port1` value of a standard controller
+const GAMEPAD: u8 = 1;
+
+bitflags! {
+    /// Commands an input log line issues before its frame runs
+    #[derive(Default)]
+    pub struct MovieCommands: u8 {
+        const SOFT_RESET     = 0b00000001;
+        const HARD_RESET     = 0b00000010;
+        const FDS_INSERT     = 0b00000100;
+        const FDS_SELECT     = 0b00001000;
+        const VS_INSERT_COIN = 0b00010000;
+    }
+}
+
+/// One line of the input log
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MovieFrame {
+    pub commands: MovieCommands,
+    pub buttons: [Buttons; PLAYERS],
+    /// CRC-32 of the work RAM once the frame ran
+    pub ram_hash: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    emulator_version: u32,
+    rerecord_count: u32,
+    pal: bool,
+    rom_filename: String,
+    rom_checksum: [u8; 16],
+    guid: String,
+    four_score: bool,
+    comments: Vec<String>,
+    subtitles: Vec<String>,
+    frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    /// An empty movie for the game loaded in `nes`
+    pub fn new(nes: &Nes) -> Result<Self> {
+        let four_score = match nes.input_devices() {
+            DeviceSetup::StandardControllers => false,
+            DeviceSetup::FourScore => true,
+            devices => {
+                return Err(Error::Unsupported(format!(
+                    "movies can't record {devices:?} input"
+                )))
+            }
+        };
+        let rom_checksum = nes.cartridge().md5();
+
+        Ok(Self {
+            emulator_version: emulator_version(),
+            rerecord_count: 0,
+            pal: nes.region() == Region::Pal,
+            rom_filename: String::new(),
+            rom_checksum,
+            guid: guid(&rom_checksum),
+            four_score,
+            comments: Vec::new(),
+            subtitles: Vec::new(),
+            frames: Vec::new(),
+        })
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        Ok(std::fs::write(path, self.to_fm2())?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut movie = Self {
+            emulator_version: 0,
+            rerecord_count: 0,
+            pal: false,
+            rom_filename: String::new(),
+            rom_checksum: [0; 16],
+            guid: String::new(),
+            four_score: false,
+            comments: Vec::new(),
+            subtitles: Vec::new(),
+            frames: Vec::new(),
+        };
+        let mut version = None;
+        let mut rom_checksum = None;
+        let mut ports = [GAMEPAD; 2];
+        let mut ram_hashes = BTreeMap::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if let Some(fields) = line.strip_prefix('|') {
+                let frame = movie.parse_frame(fields, ports).ok_or_else(|| {
+                    Error::Illegal(format!("invalid input on line {}: {line}", index + 1))
+                })?;
+                movie.frames.push(frame);
+                continue;
+            }
+
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let number = || {
+                value.trim().parse::<u32>().map_err(|_| {
+                    Error::Illegal(format!("invalid {key} on line {}: {value}", index + 1))
+                })
+            };
+
+            match key {
+                "version" => version = Some(number()?),
+                "emuVersion" => movie.emulator_version = number()?,
+                "rerecordCount" => movie.rerecord_count = number()?,
+                "palFlag" => movie.pal = number()? != 0,
+                "romFilename" => movie.rom_filename = value.to_owned(),
+                "romChecksum" => rom_checksum = Some(parse_checksum(value)?),
+                "guid" => movie.guid = value.to_owned(),
+                "fourscore" => movie.four_score = number()? != 0,
+                "port0" | "port1" => {
+                    let port = number()?;
+                    if port > GAMEPAD as u32 {
+                        return Err(Error::Unsupported(format!(
+                            "{key} holds device {port}, only controllers are supported"
+                        )));
+                    }
+                    ports[(key == "port1") as usize] = port as u8;
+                }
+                "port2" | "FDS" | "binary" if number()? != 0 => {
+                    return Err(Error::Unsupported(format!("movies with {key} {value}")));
+                }
+                "comment" => match value.strip_prefix(RAM_HASH_COMMENT) {
+                    Some(hash) => {
+                        let (frame, hash) = parse_ram_hash(hash).ok_or_else(|| {
+                            Error::Illegal(format!("invalid RAM hash on line {}", index + 1))
+                        })?;
+                        ram_hashes.insert(frame, hash);
+                    }
+                    None => movie.comments.push(value.to_owned()),
+                },
+                "subtitle" => movie.subtitles.push(value.to_owned()),
+                _ => {}
+            }
+        }
+
+        match version {
+            Some(FM2_VERSION) => {}
+            Some(version) => {
+                return Err(Error::Unsupported(format!("fm2 version {version}")));
+            }
+            None => return Err(Error::Illegal("not an fm2 movie".to_owned())),
+        }
+        movie.rom_checksum =
+            rom_checksum.ok_or_else(|| Error::Illegal("movie has no romChecksum".to_owned()))?;
+
+        for (frame, hash) in ram_hashes {
+            let frame = movie.frames.get_mut(frame).ok_or_else(|| {
+                Error::Illegal(format!("RAM hash for frame {frame}, past the end"))
+            })?;
+            frame.ram_hash = Some(hash);
+        }
+
+        Ok(movie)
+    }
+
+    /// Input line fields after the leading `|`: the commands, one field per
+    /// controller and the expansion port
+    fn parse_frame(&self, fields: &str, ports: [u8; 2]) -> Option<MovieFrame> {
+        let mut fields = fields.split('|');
+        let mut frame = MovieFrame {
+            commands: MovieCommands::from_bits_truncate(fields.next()?.trim().parse().ok()?),
+            ..Default::default()
+        };
+
+        let (players, ports) = match self.four_score {
+            true => (PLAYERS, [GAMEPAD; PLAYERS]),
+            false => (2, [ports[0], ports[1], 0, 0]),
+        };
+        for (buttons, port) in frame.buttons.iter_mut().zip(ports).take(players) {
+            let field = fields.next()?;
+            if port == GAMEPAD {
+                *buttons = parse_buttons(field)?;
+            }
+        }
+
+        Some(frame)
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut text = String::new();
+        let flag = |value: bool| value as u8;
+        // writing to a `String` can't fail
+        let _ = writeln!(text, "version {FM2_VERSION}");
+        let _ = writeln!(text, "emuVersion {}", self.emulator_version);
+        let _ = writeln!(text, "rerecordCount {}", self.rerecord_count);
+        let _ = writeln!(text, "palFlag {}", flag(self.pal));
+        let _ = writeln!(text, "romFilename {}", self.rom_filename);
+        let _ = writeln!(
+            text,
+            "romChecksum {CHECKSUM_PREFIX}{}",
+            base64(&self.rom_checksum)
+        );
+        let _ = writeln!(text, "guid {}", self.guid);
+        let _ = writeln!(text, "fourscore {}", flag(self.four_score));
+        let _ = writeln!(text, "microphone 0");
+        let _ = writeln!(text, "port0 {GAMEPAD}");
+        let _ = writeln!(text, "port1 {GAMEPAD}");
+        let _ = writeln!(text, "port2 0");
+        let _ = writeln!(text, "FDS 0");
+        let _ = writeln!(text, "NewPPU 0");
+        for comment in &self.comments {
+            let _ = writeln!(text, "comment {comment}");
+        }
+        for subtitle in &self.subtitles {
+            let _ = writeln!(text, "subtitle {subtitle}");
+        }
+        for (index, frame) in self.frames.iter().enumerate() {
+            if let Some(hash) = frame.ram_hash {
+                let _ = writeln!(text, "comment {RAM_HASH_COMMENT}{index} {hash:08X}");
+            }
+        }
+
+        let players = match self.four_score {
+            true => PLAYERS,
+            false => 2,
+        };
+        for frame in &self.frames {
+            let _ = write!(text, "|{}|", frame.commands.bits());
+            for buttons in &frame.buttons[..players] {
+                text.push_str(&format_buttons(*buttons));
+                text.push('|');
+            }
+            text.push_str("|\n");
+        }
+
+        text
+    }
+
+    pub fn rerecord_count(&self) -> u32 {
+        self.rerecord_count
+    }
+
+    pub fn set_rerecord_count(&mut self, rerecord_count: u32) {
+        self.rerecord_count = rerecord_count;
+    }
+
+    pub fn is_pal(&self) -> bool {
+        self.pal
+    }
+
+    pub fn rom_filename(&self) -> &str {
+        &self.rom_filename
+    }
+
+    pub fn set_rom_filename(&mut self, rom_filename: &str) {
+        self.rom_filename = rom_filename.to_owned();
+    }
+
+    /// MD5 of the PRG and CHR data of the ROM the movie was recorded on
+    pub fn rom_checksum(&self) -> [u8; 16] {
+        self.rom_checksum
+    }
+
+    pub fn guid(&self) -> &str {
+        &self.guid
+    }
+
+    pub fn four_score(&self) -> bool {
+        self.four_score
+    }
+
+    pub fn comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    pub fn add_comment(&mut self, comment: &str) {
+        self.comments.push(comment.to_owned());
+    }
+
+    pub fn subtitles(&self) -> &[String] {
+        &self.subtitles
+    }
+
+    pub fn frames(&self) -> &[MovieFrame] {
+        &self.frames
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+/// Runs frames with the given input, recording them into a movie
+#[derive(Debug)]
+pub struct MovieRecorder {
+    movie: Movie,
+    ram_hashes: bool,
+}
+
+impl MovieRecorder {
+    pub fn new(nes: &Nes) -> Result<Self> {
+        Ok(Self {
+            movie: Movie::new(nes)?,
+            ram_hashes: false,
+        })
+    }
+
+    /// Stores a hash of the work RAM with every frame so playback can detect desyncs
+    pub fn with_ram_hashes(mut self, ram_hashes: bool) -> Self {
+        self.ram_hashes = ram_hashes;
+        self
+    }
+
+    /// Issues `commands`, then runs a frame with the controllers in `input`
+    pub fn run_frame(
+        &mut self,
+        nes: &mut Nes,
+        input: InputState,
+        commands: MovieCommands,
+    ) -> Result<()> {
+        let mut frame = MovieFrame {
+            commands,
+            ..Default::default()
+        };
+        for (player, buttons) in frame.buttons.iter_mut().enumerate() {
+            *buttons = input.buttons(player);
+        }
+
+        run_frame(nes, &frame)?;
+        if self.ram_hashes {
+            frame.ram_hash = Some(ram_hash(nes));
+        }
+        self.movie.frames.push(frame);
+
+        Ok(())
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn movie_mut(&mut self) -> &mut Movie {
+        &mut self.movie
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Feeds a movie's input to the console frame by frame
+#[derive(Debug)]
+pub struct MoviePlayer {
+    movie: Movie,
+    frame: usize,
+}
+
+impl MoviePlayer {
+    /// Fails unless `nes` runs the ROM, region and controllers the movie was
+    /// recorded with
+    pub fn new(movie: Movie, nes: &Nes) -> Result<Self> {
+        let rom_checksum = nes.cartridge().md5();
+        if movie.rom_checksum != rom_checksum {
+            return Err(Error::Illegal(format!(
+                "movie was recorded on the ROM with checksum {CHECKSUM_PREFIX}{}, the loaded \
+                 ROM has {CHECKSUM_PREFIX}{}",
+                base64(&movie.rom_checksum),
+                base64(&rom_checksum)
+            )));
+        }
+        if movie.pal != (nes.region() == Region::Pal) {
+            return Err(Error::Illegal(format!(
+                "movie was recorded on a {} console, the loaded ROM runs as {:?}",
+                if movie.pal { "PAL" } else { "NTSC" },
+                nes.region()
+            )));
+        }
+        let devices = match movie.four_score {
+            true => DeviceSetup::FourScore,
+            false => DeviceSetup::StandardControllers,
+        };
+        if nes.input_devices() != devices {
+            return Err(Error::Illegal(format!(
+                "movie was recorded with {devices:?}, {:?} is connected",
+                nes.input_devices()
+            )));
+        }
+
+        Ok(Self { movie, frame: 0 })
+    }
+
+    /// Index of the next frame to play
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.movie.frames.len()
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    /// Plays the next frame, checking the RAM against the hash recorded with it
+    pub fn run_frame(&mut self, nes: &mut Nes) -> Result<()> {
+        let frame =
+            self.movie.frames.get(self.frame).ok_or_else(|| {
+                Error::Illegal(format!("movie ended after {} frames", self.frame))
+            })?;
+
+        run_frame(nes, frame)?;
+        if let Some(expected) = frame.ram_hash {
+            let hash = ram_hash(nes);
+            if hash != expected {
+                return Err(Error::Illegal(format!(
+                    "movie desynced at frame {}: RAM hash is {hash:08X}, {expected:08X} was \
+                     recorded",
+                    self.frame
+                )));
+            }
+        }
+        self.frame += 1;
+
+        Ok(())
+    }
+
+    /// Plays the rest of the movie
+    pub fn run(&mut self, nes: &mut Nes) -> Result<()> {
+        while !self.is_finished() {
+            self.run_frame(nes)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn run_frame(nes: &mut Nes, frame: &MovieFrame) -> Result<()> {
+    let unsupported =
+        MovieCommands::FDS_INSERT | MovieCommands::FDS_SELECT | MovieCommands::VS_INSERT_COIN;
+    if frame.commands.intersects(unsupported) {
+        return Err(Error::Unsupported(format!(
+            "movie commands {:?}",
+            frame.commands & unsupported
+        )));
+    }
+
+    if frame.commands.contains(MovieCommands::HARD_RESET) {
+        nes.power_cycle()?;
+    } else if frame.commands.contains(MovieCommands::SOFT_RESET) {
+        nes.reset()?;
+    }
+
+    let mut input = *nes.input();
+    for (player, buttons) in frame.buttons.iter().enumerate() {
+        input.set_buttons(player, *buttons);
+    }
+    nes.set_input(input);
+
+    nes.run_frame()
+}
+
+fn ram_hash(nes: &Nes) -> u32 {
+    hash::crc32(&nes.bus().ram().dump())
+}
+
+/// `RLDUTSBA`, a `.` for every released button
+fn format_buttons(buttons: Buttons) -> String {
+    "RLDUTSBA"
+        .chars()
+        .enumerate()
+        .map(|(column, name)| {
+            let bit = Buttons::from_bits_truncate(1 << (BUTTON_COLUMNS - 1 - column));
+            if buttons.contains(bit) {
+                name
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+/// Any character other than `.` or a space marks a pressed button
+fn parse_buttons(field: &str) -> Option<Buttons> {
+    if field.is_empty() {
+        return Some(Buttons::empty());
+    }
+    if field.len() < BUTTON_COLUMNS {
+        return None;
+    }
+
+    let bits = field.bytes().take(BUTTON_COLUMNS).fold(0, |bits, column| {
+        (bits << 1) | !matches!(column, b'.' | b' ') as u8
+    });
+
+    Some(Buttons::from_bits_truncate(bits))
+}
+
+fn parse_checksum(value: &str) -> Result<[u8; 16]> {
+    let invalid = || Error::Illegal(format!("invalid romChecksum: {value}"));
+
+    let bytes = value
+        .trim()
+        .strip_prefix(CHECKSUM_PREFIX)
+        .and_then(unbase64)
+        .ok_or_else(invalid)?;
+
+    bytes.try_into().map_err(|_| invalid())
+}
+
+fn parse_ram_hash(value: &str) -> Option<(usize, u32)> {
+    let (frame, hash) = value.trim().split_once(' ')?;
+
+    Some((frame.parse().ok()?, u32::from_str_radix(hash, 16).ok()?))
+}
+
+/// The crate version as FCEUX's `emuVersion` number, 1.2.3 becomes 10203
+fn emulator_version() -> u32 {
+    env!("CARGO_PKG_VERSION")
+        .split('.')
+        .take(3)
+        .map(|part| part.parse::<u32>().unwrap_or(0))
+        .fold(0, |version, part| version * 100 + part)
+}
+
+/// A movie GUID from the ROM checksum and the current time
+fn guid(rom_checksum: &[u8]) -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos());
+    let seed = [rom_checksum, &nanos.to_le_bytes()].concat();
+    let hex: String = hash::md5(&seed)
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect();
+
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(data: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => text.push(BASE64_ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char),
+                false => text.push('='),
+            }
+        }
+    }
+
+    text
+}
+
+fn unbase64(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    let mut data = Vec::new();
+    let mut bits = 0_u32;
+    let mut count = 0;
+    for byte in text.bytes() {
+        let value = BASE64_ALPHABET.iter().position(|&c| c == byte)? as u32;
+        bits = (bits << 6) | value;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            data.push((bits >> count) as u8);
+        }
+    }
+
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64, unbase64, Movie, MovieCommands, MoviePlayer, MovieRecorder};
+    use crate::{
+        core::input::{Buttons, InputState},
+        test::nrom_with_program,
+        Nes,
+    };
+
+    /// NROM image that keeps adding the controller 1 bits it reads to `$00`
+    fn nes() -> Nes {
+        // LDA #1; STA $4016; LDA #0; STA $4016; LDA $4016; AND #1; ADC $00; STA $00; JMP $8000
+        nrom_with_program(&[
+            0xA9, 0x01, 0x8D, 0x16, 0x40, 0xA9, 0x00, 0x8D, 0x16, 0x40, 0xAD, 0x16, 0x40, 0x29,
+            0x01, 0x65, 0x00, 0x85, 0x00, 0x4C, 0x00, 0x80,
+        ])
+    }
+
+    fn record() -> Movie {
+        let mut nes = nes();
+        let mut recorder = MovieRecorder::new(&nes).unwrap().with_ram_hashes(true);
+        for frame in 0..6 {
+            let mut input = InputState::default();
+            if frame % 2 == 0 {
+                input.press(0, Buttons::A | Buttons::RIGHT);
+            }
+            let commands = match frame {
+                3 => MovieCommands::SOFT_RESET,
+                _ => MovieCommands::empty(),
+            };
+            recorder.run_frame(&mut nes, input, commands).unwrap();
+        }
+
+        recorder.finish()
+    }
+
+    #[test]
+    fn movies_round_trip_through_fm2() {
+        let movie = record();
+        let text = movie.to_fm2();
+        assert!(text.contains("\n|0|R......A|........||\n"));
+        assert!(text.contains("\n|1|........|........||\n"));
+
+        assert_eq!(Movie::parse(&text).unwrap(), movie);
+    }
+
+    #[test]
+    fn playback_reproduces_the_recording() {
+        let movie = record();
+        assert!(MoviePlayer::new(movie.clone(), &nrom_with_program(&[0xEA])).is_err());
+
+        let mut nes = nes();
+        let mut player = MoviePlayer::new(movie, &nes).unwrap();
+        player.run(&mut nes).unwrap();
+        assert!(player.run_frame(&mut nes).is_err());
+    }
+
+    #[test]
+    fn playback_detects_desyncs() {
+        let text = record()
+            .to_fm2()
+            .replacen("|0|R......A|", "|0|........|", 1);
+        let mut nes = nes();
+        let mut player = MoviePlayer::new(Movie::parse(&text).unwrap(), &nes).unwrap();
+
+        let error = player.run(&mut nes).unwrap_err();
+        assert!(format!("{error}").contains("desynced at frame 0"));
+    }
+
+    #[test]
+    fn fceux_movies_parse() {
+        let text = "version 3\nemuVersion 22020\nrerecordCount 7\npalFlag 0\n\
+                    romFilename game\nromChecksum base64:kAFQmDzST7DWlj99KOF/cg==\n\
+                    guid 1\nfourscore 0\nport0 1\nport1 0\nport2 0\ncomment author me\n\
+                    |0|.L..T...|||\n|2|......BA|||\n";
+        let movie = Movie::parse(text).unwrap();
+
+        assert_eq!(movie.rerecord_count(), 7);
+        assert_eq!(movie.comments(), ["author me"]);
+        assert_eq!(movie.frames()[0].buttons[0], Buttons::LEFT | Buttons::START);
+        assert_eq!(movie.frames()[1].commands, MovieCommands::HARD_RESET);
+        assert_eq!(movie.frames()[1].buttons[0], Buttons::A | Buttons::B);
+        assert!(Movie::parse("version 2\nromChecksum base64:AA==\n").is_err());
+    }
+
+    #[test]
+    fn base64_round_trips() {
+        assert_eq!(base64(b"abc"), "YWJj");
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(unbase64("YWI=").unwrap(), b"ab");
+    }
+}
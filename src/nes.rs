@@ -0,0 +1,384 @@
+use crate::{
+    capture::{Image, ImageOptions},
+    core::{
+        apu::DEFAULT_SAMPLE_RATE,
+        input::{DeviceSetup, InputState},
+        BuiltinPalette, Bus, Cartridge, Cpu, Frame, Palette, Region,
+    },
+    error::{Error, Result},
+    state::{ChunkTag, SaveState, SLOTS},
+};
+
+/// A complete console: CPU, bus, PPU, APU and the devices in the controller ports
+#[derive(Debug)]
+pub struct Nes {
+    cpu: Cpu,
+    cartridge: Cartridge,
+    input_devices: DeviceSetup,
+    palette: Palette,
+    input: InputState,
+    /// CPU cycles left of the instruction `step_cycle` last executed
+    pending_cycles: usize,
+    /// CRC-32 of the cartridge, save states only load on the game they came from
+    rom_hash: u32,
+    slots: Vec<Option<SaveState>>,
+}
+
+impl Nes {
+    pub fn builder() -> NesBuilder {
+        NesBuilder::default()
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    pub fn bus(&self) -> &Bus {
+        self.cpu.bus()
+    }
+
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        self.cpu.bus_mut()
+    }
+
+    pub fn region(&self) -> Region {
+        self.bus().region()
+    }
+
+    pub fn cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    /// Devices connected to the controller and expansion ports
+    pub fn input_devices(&self) -> DeviceSetup {
+        self.input_devices
+    }
+
+    /// Jumps to the reset vector, like the console's reset button
+    pub fn reset(&mut self) -> Result<()> {
+        self.pending_cycles = 0;
+
+        self.cpu.reset()
+    }
+
+    /// Turns the console off and on again. Memory and every component start over,
+    /// the region, input devices and sample rate are kept.
+    pub fn power_cycle(&mut self) -> Result<()> {
+        let bus = self.bus();
+        let bus = power_on(
+            &self.cartridge,
+            bus.region(),
+            self.input_devices,
+            &self.palette,
+            bus.apu().sample_rate(),
+        );
+        self.cpu = Cpu::new(bus);
+        self.cpu.bus_mut().set_input(&self.input);
+
+        self.reset()
+    }
+
+    /// Executes a single instruction, along with any interrupt or DMA transfer it
+    /// triggers, returning the CPU cycles it took
+    pub fn step_instruction(&mut self) -> Result<usize> {
+        let start = self.bus().cycles();
+        self.cpu.step()?;
+        self.pending_cycles = 0;
+
+        Ok(self.bus().cycles().wrapping_sub(start))
+    }
+
+    /// Advances by a single CPU cycle. The CPU works an instruction at a time, so the
+    /// next instruction runs on the first of its cycles and the rest just elapse.
+    pub fn step_cycle(&mut self) -> Result<()> {
+        match self.pending_cycles {
+            0 => {
+                let cycles = self.step_instruction()?;
+                self.pending_cycles = cycles.saturating_sub(1);
+            }
+            _ => self.pending_cycles -= 1,
+        }
+
+        Ok(())
+    }
+
+    /// Runs until the PPU finishes the current frame
+    pub fn run_frame(&mut self) -> Result<()> {
+        let frame = self.frame_count();
+        while self.frame_count() == frame {
+            self.step_instruction()?;
+        }
+
+        Ok(())
+    }
+
+    /// Number of frames completed since power on
+    pub fn frame_count(&self) -> usize {
+        self.bus().ppu().frame_count()
+    }
+
+    /// The picture as NES color indices, see `Frame`
+    pub fn frame_buffer(&self) -> &Frame {
+        self.bus().ppu().frame()
+    }
+
+    /// The picture as RGBA bytes, colored with the configured palette
+    pub fn frame_rgba(&self) -> Vec<u8> {
+        self.palette.to_rgba(self.frame_buffer())
+    }
+
+    /// The picture colored with the configured palette
+    pub fn screenshot(&self, options: ImageOptions) -> Image {
+        Image::from_frame(self.frame_buffer(), &self.palette, options)
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.bus_mut().set_palette(&palette);
+        self.palette = palette;
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.bus().apu().sample_rate()
+    }
+
+    /// Takes the audio produced since the previous call, mono and roughly in
+    /// `-1.0..=1.0`. Samples are produced at the end of every frame.
+    pub fn audio_samples(&mut self) -> Vec<f32> {
+        self.bus_mut().apu_mut().take_samples()
+    }
+
+    /// Captures the whole machine, see `SaveState` for the format
+    pub fn save_state(&self) -> SaveState {
+        let mut state = SaveState::new(self.rom_hash, self.region());
+        state.put(ChunkTag::CPU, &self.cpu);
+        state.put(ChunkTag::BUS, self.bus());
+        state.put(ChunkTag::PPU, self.bus().ppu());
+        state.put(ChunkTag::APU, self.bus().apu());
+
+        state
+    }
+
+    /// Restores a state taken from the same game with the same input devices
+    /// connected
+    pub fn load_state(&mut self, state: &SaveState) -> Result<()> {
+        if state.rom_hash() != self.rom_hash {
+            return Err(Error::Illegal(format!(
+                "save state was taken from ROM {:08X}, the loaded ROM is {:08X}",
+                state.rom_hash(),
+                self.rom_hash
+            )));
+        }
+
+        let bus = self.cpu.bus_mut();
+        bus.set_region(state.region());
+        state.get(ChunkTag::BUS, bus)?;
+        state.get(ChunkTag::PPU, bus.ppu_mut())?;
+        state.get(ChunkTag::APU, bus.apu_mut())?;
+        state.get(ChunkTag::CPU, &mut self.cpu)?;
+        self.pending_cycles = 0;
+
+        Ok(())
+    }
+
+    /// Saves into one of the `SLOTS` in-memory quick save slots
+    pub fn save_slot(&mut self, slot: usize) -> Result<()> {
+        let state = self.save_state();
+        *self.slot(slot)? = Some(state);
+
+        Ok(())
+    }
+
+    pub fn load_slot(&mut self, slot: usize) -> Result<()> {
+        let state = self
+            .slot(slot)?
+            .clone()
+            .ok_or_else(|| Error::Uninitialized(format!("save slot {slot} is empty")))?;
+
+        self.load_state(&state)
+    }
+
+    fn slot(&mut self, slot: usize) -> Result<&mut Option<SaveState>> {
+        self.slots
+            .get_mut(slot)
+            .ok_or_else(|| Error::Illegal(format!("save slot {slot} doesn't exist")))
+    }
+
+    pub fn input(&self) -> &InputState {
+        &self.input
+    }
+
+    /// Sets the controller state the game sees from now on
+    pub fn set_input(&mut self, input: InputState) {
+        self.input = input;
+        self.cpu.bus_mut().set_input(&input);
+    }
+}
+
+/// Configures and powers on a `Nes`, only the cartridge is required
+#[derive(Default)]
+pub struct NesBuilder {
+    cartridge: Option<Cartridge>,
+    region: Option<Region>,
+    input_devices: Option<DeviceSetup>,
+    palette: Option<Palette>,
+    sample_rate: Option<u32>,
+}
+
+impl NesBuilder {
+    pub fn cartridge(mut self, cartridge: Cartridge) -> Self {
+        self.cartridge = Some(cartridge);
+        self
+    }
+
+    /// Overrides the timing picked from the cartridge header
+    pub fn region(mut self, region: Region) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    /// Overrides the input devices picked from the cartridge header
+    pub fn input_devices(mut self, input_devices: DeviceSetup) -> Self {
+        self.input_devices = Some(input_devices);
+        self
+    }
+
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.palette = Some(palette);
+        self
+    }
+
+    /// Host audio rate in Hz, 44.1kHz by default
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    pub fn build(self) -> Result<Nes> {
+        let cartridge = self
+            .cartridge
+            .ok_or_else(|| Error::Uninitialized("a cartridge is required".to_owned()))?;
+
+        let region = self.region.unwrap_or_else(|| cartridge.region());
+        let input_devices = self
+            .input_devices
+            .unwrap_or_else(|| cartridge.input_devices());
+        let sample_rate = self.sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE);
+        let palette = self
+            .palette
+            .unwrap_or_else(|| Palette::from(BuiltinPalette::default()));
+        let bus = power_on(&cartridge, region, input_devices, &palette, sample_rate);
+
+        let mut nes = Nes {
+            cpu: Cpu::new(bus),
+            input_devices,
+            palette,
+            input: InputState::default(),
+            pending_cycles: 0,
+            rom_hash: cartridge.hash(),
+            slots: vec![None; SLOTS],
+            cartridge,
+        };
+        nes.reset()?;
+
+        Ok(nes)
+    }
+}
+
+fn power_on(
+    cartridge: &Cartridge,
+    region: Region,
+    devices: DeviceSetup,
+    palette: &Palette,
+    sample_rate: u32,
+) -> Bus {
+    let mut bus = Bus::new(cartridge);
+    bus.set_region(region);
+    bus.set_palette(palette);
+    bus.connect_devices(devices);
+    bus.apu_mut().set_sample_rate(sample_rate);
+
+    bus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Nes;
+    use crate::{
+        core::{Cartridge, Region},
+        state::{SaveState, SLOTS},
+        test::nrom_cartridge,
+    };
+
+    /// NROM image whose program is an endless `JMP $8000`
+    fn cartridge() -> Cartridge {
+        nrom_cartridge(&[0x4C, 0x00, 0x80])
+    }
+
+    #[test]
+    fn builder_requires_a_cartridge() {
+        assert!(Nes::builder().build().is_err());
+    }
+
+    #[test]
+    fn run_frame_produces_a_frame_of_audio() {
+        let mut nes = Nes::builder()
+            .cartridge(cartridge())
+            .region(Region::Pal)
+            .sample_rate(48_000)
+            .build()
+            .unwrap();
+
+        nes.run_frame().unwrap();
+        assert_eq!(nes.frame_count(), 1);
+        assert_eq!(nes.region(), Region::Pal);
+
+        let samples = nes.audio_samples().len();
+        assert!((955..=965).contains(&samples));
+    }
+
+    #[test]
+    fn step_cycle_spreads_instructions_over_their_cycles() {
+        let mut nes = Nes::builder().cartridge(cartridge()).build().unwrap();
+
+        nes.step_cycle().unwrap();
+        let cycles = nes.bus().cycles();
+        nes.step_cycle().unwrap();
+        nes.step_cycle().unwrap();
+        assert_eq!(nes.bus().cycles(), cycles);
+
+        nes.step_cycle().unwrap();
+        assert!(nes.bus().cycles() > cycles);
+    }
+
+    #[test]
+    fn slots_restore_the_machine() {
+        let mut nes = Nes::builder().cartridge(cartridge()).build().unwrap();
+        assert!(nes.load_slot(0).is_err());
+        assert!(nes.save_slot(SLOTS).is_err());
+
+        nes.run_frame().unwrap();
+        nes.save_slot(3).unwrap();
+        let cycles = nes.bus().cycles();
+        let program_counter = nes.cpu().program_counter.get();
+
+        nes.run_frame().unwrap();
+        nes.load_slot(3).unwrap();
+        assert_eq!(nes.frame_count(), 1);
+        assert_eq!(nes.bus().cycles(), cycles);
+        assert_eq!(nes.cpu().program_counter.get(), program_counter);
+
+        let bytes = nes.save_state().to_bytes();
+        nes.run_frame().unwrap();
+        nes.load_state(&SaveState::from_bytes(&bytes).unwrap())
+            .unwrap();
+        assert_eq!(nes.bus().cycles(), cycles);
+    }
+}
@@ -0,0 +1,247 @@
+//! Headless runs reduced to per-frame hashes, so regression tests can compare whole
+//! runs against golden files.
+
+use std::{fmt::Write, path::Path};
+
+use crate::{
+    error::{Error, Result},
+    hash,
+    movie::{Movie, MoviePlayer},
+    Nes,
+};
+
+/// Set to rewrite golden files with the hashes of the current run instead of
+/// comparing against them
+pub const UPDATE_GOLDEN_VAR: &str = "NES_UPDATE_GOLDEN";
+
+/// CRC-32s of what the console produced during a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameHashes {
+    /// The picture as little-endian color indices
+    pub video: u32,
+    /// The frame's samples as little-endian 16-bit PCM
+    pub audio: u32,
+    /// The work RAM followed by the cartridge's PRG-RAM, once the frame ran
+    pub ram: u32,
+}
+
+impl FrameHashes {
+    /// Hashes the frame `nes` just finished, draining its audio
+    pub fn capture(nes: &mut Nes) -> Self {
+        let frame = nes.frame_buffer();
+        let video: Vec<u8> = (0..frame.height())
+            .flat_map(|y| frame.scanline(y))
+            .flat_map(|pixel| pixel.to_le_bytes())
+            .collect();
+        let audio: Vec<u8> = nes
+            .bus_mut()
+            .apu_mut()
+            .take_samples_i16()
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+
+        let bus = nes.bus();
+        let mut ram = bus.ram().dump().to_vec();
+        ram.extend_from_slice(bus.prg_ram());
+
+        Self {
+            video: hash::crc32(&video),
+            audio: hash::crc32(&audio),
+            ram: hash::crc32(&ram),
+        }
+    }
+}
+
+/// Runs a console without any host, optionally driven by a movie
+#[derive(Debug)]
+pub struct HeadlessRunner {
+    nes: Nes,
+    player: Option<MoviePlayer>,
+}
+
+impl HeadlessRunner {
+    pub fn new(nes: Nes) -> Self {
+        Self { nes, player: None }
+    }
+
+    /// Plays `movie` from the next frame on, the controllers are left alone once
+    /// it ends
+    pub fn with_movie(mut self, movie: Movie) -> Result<Self> {
+        self.player = Some(MoviePlayer::new(movie, &self.nes)?);
+
+        Ok(self)
+    }
+
+    pub fn nes(&self) -> &Nes {
+        &self.nes
+    }
+
+    pub fn into_nes(self) -> Nes {
+        self.nes
+    }
+
+    /// Runs `frames` frames, hashing each of them
+    pub fn run(&mut self, frames: usize) -> Result<Vec<FrameHashes>> {
+        // audio from before the run doesn't belong to any of its frames
+        self.nes.audio_samples();
+
+        (0..frames)
+            .map(|_| {
+                match &mut self.player {
+                    Some(player) if !player.is_finished() => player.run_frame(&mut self.nes)?,
+                    _ => self.nes.run_frame()?,
+                }
+
+                Ok(FrameHashes::capture(&mut self.nes))
+            })
+            .collect()
+    }
+}
+
+/// One line per frame: the frame number followed by the video, audio and RAM hashes
+pub fn format_hashes(hashes: &[FrameHashes]) -> String {
+    let mut text = String::new();
+    for (frame, hashes) in hashes.iter().enumerate() {
+        // writing to a `String` can't fail
+        let _ = writeln!(
+            text,
+            "{frame} {:08X} {:08X} {:08X}",
+            hashes.video, hashes.audio, hashes.ram
+        );
+    }
+
+    text
+}
+
+pub fn parse_hashes(text: &str) -> Result<Vec<FrameHashes>> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(index, line)| {
+            let invalid = || Error::Illegal(format!("invalid frame hashes: {line}"));
+            let hash = |value: Option<&str>| {
+                u32::from_str_radix(value.ok_or_else(invalid)?, 16).map_err(|_| invalid())
+            };
+
+            let mut fields = line.split_whitespace();
+            if fields.next() != Some(index.to_string().as_str()) {
+                return Err(invalid());
+            }
+
+            Ok(FrameHashes {
+                video: hash(fields.next())?,
+                audio: hash(fields.next())?,
+                ram: hash(fields.next())?,
+            })
+        })
+        .collect()
+}
+
+/// Compares a run against the golden file at `path`, or rewrites the file when
+/// `UPDATE_GOLDEN_VAR` is set
+pub fn check_golden<P: AsRef<Path>>(path: P, hashes: &[FrameHashes]) -> Result<()> {
+    let path = path.as_ref();
+    if std::env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        return Ok(std::fs::write(path, format_hashes(hashes))?);
+    }
+
+    let golden = parse_hashes(&std::fs::read_to_string(path)?)?;
+    if let Some(frame) =
+        (0..golden.len().max(hashes.len())).find(|&frame| golden.get(frame) != hashes.get(frame))
+    {
+        let describe = |hashes: Option<&FrameHashes>| match hashes {
+            Some(hashes) => format!(
+                "video {:08X} audio {:08X} ram {:08X}",
+                hashes.video, hashes.audio, hashes.ram
+            ),
+            None => "nothing".to_owned(),
+        };
+
+        return Err(Error::Illegal(format!(
+            "{} differs at frame {frame}: expected {}, got {}. Run with {UPDATE_GOLDEN_VAR}=1 \
+             to accept the new output",
+            path.display(),
+            describe(golden.get(frame)),
+            describe(hashes.get(frame))
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_golden, format_hashes, parse_hashes, FrameHashes, HeadlessRunner};
+    use crate::{core::Cartridge, movie::Movie, Nes};
+    use std::path::Path;
+
+    const GOLDEN_FRAMES: usize = 60;
+
+    fn nes(rom: &str) -> Nes {
+        let cartridge = Cartridge::try_from(Path::new("test_data").join(rom).as_path()).unwrap();
+
+        Nes::builder().cartridge(cartridge).build().unwrap()
+    }
+
+    fn run(rom: &str) -> Vec<FrameHashes> {
+        HeadlessRunner::new(nes(rom)).run(GOLDEN_FRAMES).unwrap()
+    }
+
+    #[test]
+    fn hashes_round_trip_through_text() {
+        let hashes = vec![
+            FrameHashes {
+                video: 0x0123_4567,
+                audio: 0x89AB_CDEF,
+                ram: 0,
+            };
+            2
+        ];
+
+        assert_eq!(parse_hashes(&format_hashes(&hashes)).unwrap(), hashes);
+        assert!(parse_hashes("1 0 0 0").is_err());
+    }
+
+    #[test]
+    fn runs_are_deterministic() {
+        assert_eq!(run("cpu_dummy_reads.nes"), run("cpu_dummy_reads.nes"));
+    }
+
+    #[test]
+    fn test_roms_match_their_golden_hashes() {
+        for rom in ["nestest", "cpu_dummy_reads"] {
+            let hashes = run(&format!("{rom}.nes"));
+            let golden = Path::new("test_data/golden").join(format!("{rom}.hashes"));
+
+            if let Err(error) = check_golden(golden, &hashes) {
+                panic!("{error}");
+            }
+        }
+    }
+
+    /// nestest driven through its menu: the cursor moves down, then Start runs the
+    /// selected tests
+    #[test]
+    fn movies_match_their_golden_hashes() {
+        let movie = Movie::open("test_data/nestest.fm2").unwrap();
+        let frames = movie.len();
+        let hashes = HeadlessRunner::new(nes("nestest.nes"))
+            .with_movie(movie)
+            .unwrap()
+            .run(frames)
+            .unwrap();
+
+        assert_ne!(
+            hashes[GOLDEN_FRAMES - 1],
+            run("nestest.nes")[GOLDEN_FRAMES - 1]
+        );
+        if let Err(error) = check_golden("test_data/golden/nestest_movie.hashes", &hashes) {
+            panic!("{error}");
+        }
+    }
+}
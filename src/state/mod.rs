@@ -0,0 +1,195 @@
+mod codec;
+mod rewind;
+mod save_state;
+
+use crate::error::{Error, Result};
+
+pub use codec::{StateReader, StateWriter};
+pub use rewind::Rewind;
+pub use save_state::{ChunkTag, SaveState, FORMAT_VERSION, SLOTS};
+
+/// Components that can be written into and restored from a save state
+pub trait Stateful {
+    fn save_state(&self, writer: &mut StateWriter);
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()>;
+}
+
+/// Implements `Stateful` for a struct by saving the listed fields in order. Fields
+/// left out keep their current value when a state is loaded.
+macro_rules! stateful {
+    ($type:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::state::Stateful for $type {
+            fn save_state(&self, writer: &mut $crate::state::StateWriter) {
+                $($crate::state::Stateful::save_state(&self.$field, writer);)*
+            }
+
+            fn load_state(
+                &mut self,
+                reader: &mut $crate::state::StateReader,
+            ) -> $crate::error::Result<()> {
+                $($crate::state::Stateful::load_state(&mut self.$field, reader)?;)*
+
+                Ok(())
+            }
+        }
+    };
+}
+
+/// Implements `Stateful` for `bitflags` types through their raw bits
+macro_rules! stateful_bitflags {
+    ($($type:ty),* $(,)?) => {
+        $(
+            impl $crate::state::Stateful for $type {
+                fn save_state(&self, writer: &mut $crate::state::StateWriter) {
+                    $crate::state::Stateful::save_state(&self.bits(), writer);
+                }
+
+                fn load_state(
+                    &mut self,
+                    reader: &mut $crate::state::StateReader,
+                ) -> $crate::error::Result<()> {
+                    *self = Self::from_bits_truncate(reader.read_u8()?);
+
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+pub(crate) use stateful;
+pub(crate) use stateful_bitflags;
+
+/// Error for an enum discriminant a save state can't contain
+pub(crate) fn invalid(what: &str, value: u8) -> Error {
+    Error::Illegal(format!("save state holds an invalid {what}: {value}"))
+}
+
+impl Stateful for u8 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(*self);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        *self = reader.read_u8()?;
+
+        Ok(())
+    }
+}
+
+impl Stateful for u16 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(*self);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        *self = reader.read_u16()?;
+
+        Ok(())
+    }
+}
+
+impl Stateful for u32 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u32(*self);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        *self = reader.read_u32()?;
+
+        Ok(())
+    }
+}
+
+/// Saved as 64 bits so states move between 32 and 64-bit hosts
+impl Stateful for usize {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u64(*self as u64);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        *self = reader.read_u64()? as usize;
+
+        Ok(())
+    }
+}
+
+impl Stateful for bool {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(*self as u8);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        *self = reader.read_u8()? != 0;
+
+        Ok(())
+    }
+}
+
+impl<T: Stateful + Default> Stateful for Option<T> {
+    fn save_state(&self, writer: &mut StateWriter) {
+        match self {
+            Some(value) => {
+                writer.write_u8(1);
+                value.save_state(writer);
+            }
+            None => writer.write_u8(0),
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        *self = match reader.read_u8()? {
+            0 => None,
+            _ => {
+                let mut value = T::default();
+                value.load_state(reader)?;
+                Some(value)
+            }
+        };
+
+        Ok(())
+    }
+}
+
+impl<A: Stateful, B: Stateful> Stateful for (A, B) {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.0.save_state(writer);
+        self.1.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.0.load_state(reader)?;
+        self.1.load_state(reader)
+    }
+}
+
+impl<T: Stateful, const N: usize> Stateful for [T; N] {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.iter().for_each(|value| value.save_state(writer));
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        self.iter_mut()
+            .try_for_each(|value| value.load_state(reader))
+    }
+}
+
+impl<T: Stateful + Default> Stateful for Vec<T> {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u32(self.len() as u32);
+        self.iter().for_each(|value| value.save_state(writer));
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<()> {
+        let len = reader.read_u32()? as usize;
+        self.clear();
+        for _ in 0..len {
+            let mut value = T::default();
+            value.load_state(reader)?;
+            self.push(value);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,420 @@
+use std::collections::VecDeque;
+
+use super::SaveState;
+use crate::{
+    core::input::InputState,
+    error::{Error, Result},
+    Nes,
+};
+
+/// A full capture and the captures that are stored as deltas against it
+#[derive(Debug, Clone)]
+struct Keyframe {
+    frame: usize,
+    /// Run-length encoded state bytes
+    data: Vec<u8>,
+    /// Frame and run-length encoded XOR of the state against the keyframe
+    deltas: Vec<(usize, Vec<u8>)>,
+}
+
+impl Keyframe {
+    fn state(&self) -> Result<Vec<u8>> {
+        decode(&self.data)
+    }
+}
+
+/// Ring buffer of past states for stepping backward in time.
+///
+/// `record` is called once after every frame. Every `interval` frames it captures
+/// the machine, storing every `deltas_per_keyframe + 1`th capture in full and the
+/// rest as run-length encoded XOR deltas against it. The input of every frame is
+/// kept as well, so `rewind` can load the closest capture and resimulate the frames
+/// up to the exact one asked for. The oldest captures are dropped once more than
+/// `capacity` are held.
+#[derive(Debug, Clone)]
+pub struct Rewind {
+    interval: usize,
+    deltas_per_keyframe: usize,
+    capacity: usize,
+    keyframes: VecDeque<Keyframe>,
+    /// Input in effect while running each frame from `inputs_start` on, `None` for
+    /// frames that ran without being recorded
+    inputs: VecDeque<Option<InputState>>,
+    inputs_start: usize,
+}
+
+impl Rewind {
+    pub const DEFAULT_INTERVAL: usize = 10;
+    pub const DEFAULT_DELTAS_PER_KEYFRAME: usize = 15;
+
+    /// Captures every `interval` frames, holding up to `capacity` captures
+    pub fn new(interval: usize, capacity: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            deltas_per_keyframe: Self::DEFAULT_DELTAS_PER_KEYFRAME,
+            capacity: capacity.max(1),
+            keyframes: VecDeque::new(),
+            inputs: VecDeque::new(),
+            inputs_start: 0,
+        }
+    }
+
+    /// Number of captures stored as deltas between two full ones
+    pub fn with_deltas_per_keyframe(mut self, deltas_per_keyframe: usize) -> Self {
+        self.deltas_per_keyframe = deltas_per_keyframe;
+        self
+    }
+
+    /// Number of captures held
+    pub fn len(&self) -> usize {
+        self.keyframes
+            .iter()
+            .map(|keyframe| keyframe.deltas.len() + 1)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// Bytes taken by the compressed captures
+    pub fn memory_usage(&self) -> usize {
+        self.keyframes
+            .iter()
+            .map(|keyframe| {
+                keyframe.data.len() + keyframe.deltas.iter().map(|(_, d)| d.len()).sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Frame of the oldest capture, the furthest back `rewind` can go
+    pub fn oldest_frame(&self) -> Option<usize> {
+        self.keyframes.front().map(|keyframe| keyframe.frame)
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+        self.inputs.clear();
+    }
+
+    /// Notes the input of the frame that just ran and captures the machine when
+    /// `interval` frames passed since the previous capture
+    pub fn record(&mut self, nes: &Nes) {
+        let frame = nes.frame_count();
+        if self.inputs.is_empty() {
+            self.inputs_start = frame.saturating_sub(1);
+        }
+        if frame > self.inputs_start {
+            let index = frame - 1 - self.inputs_start;
+            if index >= self.inputs.len() {
+                self.inputs.resize(index + 1, None);
+            }
+            self.inputs[index] = Some(*nes.input());
+        }
+
+        let due = match self.latest_frame() {
+            Some(latest) => frame >= latest + self.interval,
+            None => true,
+        };
+        if due {
+            self.capture(frame, nes.save_state().to_bytes());
+        }
+    }
+
+    /// Returns the machine to the state it had `frames` frames ago
+    pub fn rewind(&mut self, nes: &mut Nes, frames: usize) -> Result<()> {
+        let current = nes.frame_count();
+        let target = current.checked_sub(frames).ok_or_else(|| {
+            Error::Illegal(format!("can't rewind {frames} frames from frame {current}"))
+        })?;
+
+        let (frame, state) = self.closest(target)?.ok_or_else(|| {
+            Error::Illegal(format!(
+                "frame {target} is older than the rewind buffer, which starts at frame {}",
+                self.oldest_frame().unwrap_or(current)
+            ))
+        })?;
+        let inputs = (frame..target)
+            .map(|frame| {
+                frame
+                    .checked_sub(self.inputs_start)
+                    .and_then(|index| self.inputs.get(index).copied().flatten())
+                    .ok_or_else(|| {
+                        Error::Illegal(format!(
+                            "the input of frame {frame} wasn't recorded, can't resimulate it"
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let input = *nes.input();
+        nes.load_state(&SaveState::from_bytes(&state)?)?;
+        for input in inputs {
+            nes.set_input(input);
+            nes.run_frame()?;
+        }
+        nes.set_input(input);
+        // the audio of resimulated frames was heard already
+        nes.audio_samples();
+
+        self.truncate(target);
+
+        Ok(())
+    }
+
+    fn latest_frame(&self) -> Option<usize> {
+        let keyframe = self.keyframes.back()?;
+
+        Some(
+            keyframe
+                .deltas
+                .last()
+                .map_or(keyframe.frame, |&(frame, _)| frame),
+        )
+    }
+
+    fn capture(&mut self, frame: usize, state: Vec<u8>) {
+        let delta = self.keyframes.back().and_then(|keyframe| {
+            if keyframe.deltas.len() >= self.deltas_per_keyframe {
+                return None;
+            }
+
+            let base = keyframe.state().ok()?;
+            (base.len() == state.len()).then(|| xor(&base, &state))
+        });
+
+        match delta {
+            Some(delta) => {
+                let keyframe = self.keyframes.back_mut().unwrap();
+                keyframe.deltas.push((frame, encode(&delta)));
+            }
+            None => self.keyframes.push_back(Keyframe {
+                frame,
+                data: encode(&state),
+                deltas: Vec::new(),
+            }),
+        }
+
+        while self.len() > self.capacity && self.keyframes.len() > 1 {
+            self.keyframes.pop_front();
+        }
+        if let Some(oldest) = self.oldest_frame() {
+            let stale = oldest
+                .saturating_sub(self.inputs_start)
+                .min(self.inputs.len());
+            self.inputs.drain(..stale);
+            self.inputs_start += stale;
+        }
+    }
+
+    /// The latest capture at or before `target`
+    fn closest(&self, target: usize) -> Result<Option<(usize, Vec<u8>)>> {
+        let Some(keyframe) = self
+            .keyframes
+            .iter()
+            .rev()
+            .find(|keyframe| keyframe.frame <= target)
+        else {
+            return Ok(None);
+        };
+
+        let state = keyframe.state()?;
+        let capture = match keyframe
+            .deltas
+            .iter()
+            .rev()
+            .find(|&&(frame, _)| frame <= target)
+        {
+            Some((frame, delta)) => (*frame, xor(&state, &decode(delta)?)),
+            None => (keyframe.frame, state),
+        };
+
+        Ok(Some(capture))
+    }
+
+    /// Forgets captures and input after `frame`, they belong to a future that was
+    /// rewound away
+    fn truncate(&mut self, frame: usize) {
+        while self
+            .keyframes
+            .back()
+            .is_some_and(|keyframe| keyframe.frame > frame)
+        {
+            self.keyframes.pop_back();
+        }
+        if let Some(keyframe) = self.keyframes.back_mut() {
+            keyframe
+                .deltas
+                .retain(|&(delta_frame, _)| delta_frame <= frame);
+        }
+
+        self.inputs
+            .truncate(frame.saturating_sub(self.inputs_start));
+    }
+}
+
+impl Default for Rewind {
+    /// Ten minutes of NTSC play
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_INTERVAL, 3600)
+    }
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(a, b)| a ^ b).collect()
+}
+
+/// PackBits-style run-length encoding: a control byte below 0x80 is followed by
+/// that many plus one literal bytes, one from 0x80 up repeats the next byte
+/// `control - 0x7D` times
+fn encode(data: &[u8]) -> Vec<u8> {
+    const MAX_RUN: usize = 130;
+    const MAX_LITERALS: usize = 128;
+
+    let mut encoded = Vec::new();
+    let mut literals = 0..0;
+    let mut i = 0;
+    while i < data.len() {
+        let run = data[i..]
+            .iter()
+            .take(MAX_RUN)
+            .take_while(|&&byte| byte == data[i])
+            .count();
+
+        if run >= 3 {
+            flush_literals(&mut encoded, &data[literals.clone()]);
+            encoded.extend([(run + 0x7D) as u8, data[i]]);
+            i += run;
+            literals = i..i;
+        } else {
+            i += 1;
+            literals.end = i;
+            if literals.len() == MAX_LITERALS {
+                flush_literals(&mut encoded, &data[literals.clone()]);
+                literals = i..i;
+            }
+        }
+    }
+    flush_literals(&mut encoded, &data[literals]);
+
+    encoded
+}
+
+fn flush_literals(encoded: &mut Vec<u8>, literals: &[u8]) {
+    if !literals.is_empty() {
+        encoded.push((literals.len() - 1) as u8);
+        encoded.extend_from_slice(literals);
+    }
+}
+
+fn decode(data: &[u8]) -> Result<Vec<u8>> {
+    let corrupt = || Error::Illegal("rewind buffer is corrupt".to_owned());
+
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i] as usize;
+        i += 1;
+        if control < 0x80 {
+            let literals = data.get(i..i + control + 1).ok_or_else(corrupt)?;
+            decoded.extend_from_slice(literals);
+            i += control + 1;
+        } else {
+            let byte = *data.get(i).ok_or_else(corrupt)?;
+            decoded.extend(std::iter::repeat_n(byte, control - 0x7D));
+            i += 1;
+        }
+    }
+
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, Rewind};
+    use crate::{
+        core::input::{Buttons, InputState},
+        test::nrom_with_program,
+        Nes,
+    };
+
+    /// NROM image whose program is an endless `INC $00; JMP $8000`
+    fn nes() -> Nes {
+        nrom_with_program(&[0xE6, 0x00, 0x4C, 0x00, 0x80])
+    }
+
+    #[test]
+    fn run_length_encoding_round_trips() {
+        let mut data = vec![0; 1000];
+        data.extend(0..=255);
+        data.extend([7, 7, 1, 7, 7, 7]);
+
+        let encoded = encode(&data);
+        assert!(encoded.len() < 300);
+        assert_eq!(decode(&encoded).unwrap(), data);
+        assert!(decode(&[0x05, 1]).is_err());
+    }
+
+    #[test]
+    fn rewinding_is_frame_exact() {
+        let mut nes = nes();
+        let mut rewind = Rewind::new(4, 100).with_deltas_per_keyframe(2);
+        let mut states = Vec::new();
+        for _ in 0..20 {
+            nes.run_frame().unwrap();
+            rewind.record(&nes);
+            states.push(nes.save_state());
+        }
+
+        rewind.rewind(&mut nes, 7).unwrap();
+        assert_eq!(nes.frame_count(), 13);
+        assert_eq!(nes.save_state(), states[12]);
+
+        rewind.rewind(&mut nes, 12).unwrap();
+        assert_eq!(nes.save_state(), states[0]);
+        assert!(rewind.rewind(&mut nes, 1).is_err());
+    }
+
+    #[test]
+    fn old_captures_are_dropped() {
+        let mut nes = nes();
+        let mut rewind = Rewind::new(1, 6).with_deltas_per_keyframe(2);
+        for _ in 0..20 {
+            nes.run_frame().unwrap();
+            rewind.record(&nes);
+        }
+
+        assert!(rewind.len() <= 6);
+        assert_eq!(rewind.oldest_frame(), Some(16));
+        assert!(rewind.rewind(&mut nes, 10).is_err());
+        rewind.rewind(&mut nes, 4).unwrap();
+        assert_eq!(nes.frame_count(), 16);
+    }
+
+    #[test]
+    fn frames_run_without_recording_can_only_be_reached_through_captures() {
+        let mut nes = nes();
+        let mut rewind = Rewind::new(4, 100);
+        let mut pressed = InputState::default();
+        pressed.press(0, Buttons::B);
+        nes.set_input(pressed);
+
+        for frame in 1..=13 {
+            nes.run_frame().unwrap();
+            if !(6..=8).contains(&frame) {
+                rewind.record(&nes);
+            }
+        }
+
+        // from the capture at frame 5, frames 5 to 7 would have to be resimulated
+        assert!(rewind.rewind(&mut nes, 5).is_err());
+        assert_eq!(nes.frame_count(), 13);
+
+        let mut current = InputState::default();
+        current.press(0, Buttons::A);
+        nes.set_input(current);
+        rewind.rewind(&mut nes, 3).unwrap();
+        assert_eq!(nes.frame_count(), 10);
+        assert_eq!(*nes.input(), current);
+    }
+}
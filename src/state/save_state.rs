@@ -0,0 +1,259 @@
+use std::path::Path;
+
+use super::{StateReader, StateWriter, Stateful};
+use crate::{
+    core::{Region, PRG_RAM_SIZE, RAM_SIZE},
+    error::{Error, Result},
+};
+
+const MAGIC: [u8; 8] = *b"NESSTATE";
+/// Version of the layout below, bumped whenever a component's saved fields change
+pub const FORMAT_VERSION: u16 = 2;
+/// Number of quick save slots a `Nes` keeps in memory
+pub const SLOTS: usize = 10;
+
+/// Identifies the component a chunk holds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkTag(pub [u8; 4]);
+
+impl ChunkTag {
+    pub const CPU: Self = Self(*b"CPU ");
+    pub const BUS: Self = Self(*b"BUS ");
+    pub const PPU: Self = Self(*b"PPU ");
+    pub const APU: Self = Self(*b"APU ");
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Chunk {
+    tag: ChunkTag,
+    data: Vec<u8>,
+}
+
+/// A snapshot of the whole machine.
+///
+/// The binary layout starts with a header: the `NESSTATE` magic, the format
+/// version, the emulator version that wrote it, the CRC-32 of the ROM and the
+/// region. Tagged, length-prefixed chunks follow, one per component, so readers can
+/// skip the ones they don't know about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveState {
+    format_version: u16,
+    emulator_version: String,
+    rom_hash: u32,
+    region: Region,
+    chunks: Vec<Chunk>,
+}
+
+impl SaveState {
+    pub fn new(rom_hash: u32, region: Region) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            emulator_version: env!("CARGO_PKG_VERSION").to_owned(),
+            rom_hash,
+            region,
+            chunks: Vec::new(),
+        }
+    }
+
+    pub fn format_version(&self) -> u16 {
+        self.format_version
+    }
+
+    pub fn emulator_version(&self) -> &str {
+        &self.emulator_version
+    }
+
+    /// CRC-32 of the PRG and CHR data of the ROM the state was taken from
+    pub fn rom_hash(&self) -> u32 {
+        self.rom_hash
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// Stores a component under `tag`, replacing a previous chunk with the same tag
+    pub fn put<T: Stateful + ?Sized>(&mut self, tag: ChunkTag, component: &T) {
+        let mut writer = StateWriter::default();
+        component.save_state(&mut writer);
+        let data = writer.into_inner();
+
+        match self.chunks.iter_mut().find(|chunk| chunk.tag == tag) {
+            Some(chunk) => chunk.data = data,
+            None => self.chunks.push(Chunk { tag, data }),
+        }
+    }
+
+    /// Restores a component from the chunk stored under `tag`
+    pub fn get<T: Stateful + ?Sized>(&self, tag: ChunkTag, component: &mut T) -> Result<()> {
+        let chunk = self
+            .chunks
+            .iter()
+            .find(|chunk| chunk.tag == tag)
+            .ok_or_else(|| {
+                Error::Illegal(format!(
+                    "save state has no {} chunk",
+                    String::from_utf8_lossy(&tag.0).trim_end()
+                ))
+            })?;
+
+        component.load_state(&mut StateReader::new(&chunk.data))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::default();
+        writer.write_bytes(&MAGIC);
+        writer.write_u16(self.format_version);
+        writer.write_u8(self.emulator_version.len() as u8);
+        writer.write_bytes(self.emulator_version.as_bytes());
+        writer.write_u32(self.rom_hash);
+        self.region.save_state(&mut writer);
+
+        for chunk in &self.chunks {
+            writer.write_bytes(&chunk.tag.0);
+            writer.write_u32(chunk.data.len() as u32);
+            writer.write_bytes(&chunk.data);
+        }
+
+        writer.into_inner()
+    }
+
+    /// Parses a state, migrating it when it was written in an older format
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = StateReader::new(data);
+        if reader.read_bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(Error::Unsupported("not a save state".to_owned()));
+        }
+
+        let format_version = reader.read_u16()?;
+        let len = reader.read_u8()? as usize;
+        let emulator_version = String::from_utf8_lossy(reader.read_bytes(len)?).into_owned();
+        let rom_hash = reader.read_u32()?;
+        let mut region = Region::default();
+        region.load_state(&mut reader)?;
+
+        let mut chunks = Vec::new();
+        while !reader.is_empty() {
+            let mut tag = [0; 4];
+            tag.copy_from_slice(reader.read_bytes(4)?);
+            let len = reader.read_u32()? as usize;
+            let data = reader.read_bytes(len)?.to_vec();
+
+            chunks.push(Chunk {
+                tag: ChunkTag(tag),
+                data,
+            });
+        }
+
+        let state = Self {
+            format_version,
+            emulator_version,
+            rom_hash,
+            region,
+            chunks,
+        };
+
+        state.migrate()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        Ok(std::fs::write(path, self.to_bytes())?)
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    /// Brings a state written by another version up to `FORMAT_VERSION`. Every
+    /// format change adds a step here, states from before the oldest step or from
+    /// a newer emulator are rejected.
+    fn migrate(mut self) -> Result<Self> {
+        loop {
+            match self.format_version {
+                FORMAT_VERSION => return Ok(self),
+                1 => self.add_prg_ram(),
+                version if version > FORMAT_VERSION => {
+                    return Err(Error::Unsupported(format!(
+                        "save state format {version} was written by emulator {} and is newer \
+                         than the supported format {FORMAT_VERSION}",
+                        self.emulator_version
+                    )))
+                }
+                version => {
+                    return Err(Error::Unsupported(format!(
+                        "save state format {version} written by emulator {} can no longer be \
+                         loaded, the oldest supported format is 1",
+                        self.emulator_version
+                    )))
+                }
+            }
+        }
+    }
+
+    /// Format 2 added the cartridge's PRG-RAM to the bus, right after the work RAM.
+    /// Older states start with it cleared.
+    fn add_prg_ram(&mut self) {
+        if let Some(chunk) = self
+            .chunks
+            .iter_mut()
+            .find(|chunk| chunk.tag == ChunkTag::BUS)
+        {
+            let offset = RAM_SIZE.min(chunk.data.len());
+            let mut prg_ram = (PRG_RAM_SIZE as u32).to_le_bytes().to_vec();
+            prg_ram.resize(4 + PRG_RAM_SIZE, 0);
+            chunk.data.splice(offset..offset, prg_ram);
+        }
+
+        self.format_version = 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChunkTag, SaveState, FORMAT_VERSION};
+    use crate::core::{Region, PRG_RAM_SIZE, RAM_SIZE};
+
+    #[test]
+    fn states_round_trip_through_bytes() {
+        let mut state = SaveState::new(0xDEAD_BEEF, Region::Pal);
+        state.put(ChunkTag::CPU, &[1_u8, 2, 3]);
+        state.put(ChunkTag::PPU, &0x1234_u16);
+
+        let restored = SaveState::from_bytes(&state.to_bytes()).unwrap();
+        assert_eq!(restored, state);
+
+        let mut value = 0_u16;
+        restored.get(ChunkTag::PPU, &mut value).unwrap();
+        assert_eq!(value, 0x1234);
+        assert!(restored.get(ChunkTag::APU, &mut value).is_err());
+    }
+
+    #[test]
+    fn other_format_versions_are_rejected() {
+        let mut bytes = SaveState::new(0, Region::Ntsc).to_bytes();
+        bytes[8..10].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+
+        assert!(SaveState::from_bytes(&bytes).is_err());
+        assert!(SaveState::from_bytes(b"garbage").is_err());
+
+        bytes[8..10].copy_from_slice(&0_u16.to_le_bytes());
+        assert!(SaveState::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn format_1_states_gain_prg_ram() {
+        let mut state = SaveState::new(0, Region::Ntsc);
+        state.put(ChunkTag::BUS, &[[0xAA_u8; RAM_SIZE], [0xBB; RAM_SIZE]]);
+        let mut bytes = state.to_bytes();
+        bytes[8..10].copy_from_slice(&1_u16.to_le_bytes());
+
+        let state = SaveState::from_bytes(&bytes).unwrap();
+        assert_eq!(state.format_version(), FORMAT_VERSION);
+
+        let mut bus = (([0_u8; RAM_SIZE], Vec::<u8>::new()), [0_u8; RAM_SIZE]);
+        state.get(ChunkTag::BUS, &mut bus).unwrap();
+        assert_eq!(bus.0 .0, [0xAA; RAM_SIZE]);
+        assert_eq!(bus.0 .1, vec![0; PRG_RAM_SIZE]);
+        assert_eq!(bus.1, [0xBB; RAM_SIZE]);
+    }
+}
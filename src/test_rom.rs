@@ -0,0 +1,231 @@
+//! Runs accuracy test ROMs that report through blargg's `$6000` protocol: a status
+//! byte at `$6000`, the `DE B0 61` signature at `$6001-$6003` and a zero terminated
+//! message from `$6004` on.
+
+use std::path::{Path, PathBuf};
+
+use crate::{core::Cartridge, error::Result, Nes};
+
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const STATUS_RUNNING: u8 = 0x80;
+const STATUS_RESET_REQUESTED: u8 = 0x81;
+const MESSAGE_START: usize = 4;
+/// Frames to wait before pressing reset, the ROMs ask for at least 100ms
+const RESET_DELAY_FRAMES: usize = 6;
+/// ROMs that haven't written the signature by then don't speak the protocol
+const SIGNATURE_TIMEOUT_FRAMES: usize = 300;
+/// Enough for the slowest of the blargg suites
+pub const DEFAULT_MAX_FRAMES: usize = 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestStatus {
+    Passed,
+    /// The ROM's result code, usually the number of the failed test
+    Failed(u8),
+    /// Still running when the frame limit was reached
+    TimedOut,
+    /// The ROM never wrote the signature
+    NoProtocol,
+}
+
+impl TestStatus {
+    /// Anything but a pass fails, a ROM that never speaks the protocol included
+    pub fn is_failure(&self) -> bool {
+        *self != Self::Passed
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestReport {
+    pub status: TestStatus,
+    /// Text the ROM left at `$6004`
+    pub message: String,
+    pub frames: usize,
+}
+
+impl TestReport {
+    pub fn passed(&self) -> bool {
+        self.status == TestStatus::Passed
+    }
+}
+
+/// Runs the ROM in `nes` until it reports a result or `max_frames` pass, pressing
+/// reset whenever it asks for it
+pub fn run_test_rom(nes: &mut Nes, max_frames: usize) -> Result<TestReport> {
+    let mut reset_at = None;
+    let mut signature_seen = false;
+
+    for frame in 1..=max_frames {
+        nes.run_frame()?;
+
+        let prg_ram = nes.bus().prg_ram();
+        if prg_ram[1..MESSAGE_START] != SIGNATURE {
+            if !signature_seen && frame >= SIGNATURE_TIMEOUT_FRAMES {
+                return Ok(report(nes, TestStatus::NoProtocol, frame));
+            }
+            continue;
+        }
+        signature_seen = true;
+
+        match prg_ram[0] {
+            STATUS_RUNNING => {}
+            STATUS_RESET_REQUESTED => match reset_at {
+                None => reset_at = Some(frame + RESET_DELAY_FRAMES),
+                Some(at) if frame >= at => {
+                    reset_at = None;
+                    // the ROM overwrites the status once it runs again
+                    nes.reset()?;
+                }
+                Some(_) => {}
+            },
+            0 => return Ok(report(nes, TestStatus::Passed, frame)),
+            code => return Ok(report(nes, TestStatus::Failed(code), frame)),
+        }
+    }
+
+    let status = match signature_seen {
+        true => TestStatus::TimedOut,
+        false => TestStatus::NoProtocol,
+    };
+
+    Ok(report(nes, status, max_frames))
+}
+
+/// Runs every `.nes` file under `dir`, subdirectories included, in path order
+pub fn run_test_dir<P: AsRef<Path>>(
+    dir: P,
+    max_frames: usize,
+) -> Result<Vec<(PathBuf, Result<TestReport>)>> {
+    Ok(find_test_roms(dir)?
+        .into_iter()
+        .map(|path| {
+            let report = Cartridge::try_from(path.as_path())
+                .and_then(|cartridge| Nes::builder().cartridge(cartridge).build())
+                .and_then(|mut nes| run_test_rom(&mut nes, max_frames));
+
+            (path, report)
+        })
+        .collect())
+}
+
+/// Paths of the `.nes` files under `dir`, subdirectories included, sorted
+pub fn find_test_roms<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>> {
+    let mut roms = Vec::new();
+    find_roms(dir.as_ref(), &mut roms)?;
+    roms.sort();
+
+    Ok(roms)
+}
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_roms(&path, roms)?;
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("nes"))
+        {
+            roms.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn report(nes: &Nes, status: TestStatus, frames: usize) -> TestReport {
+    let text = &nes.bus().prg_ram()[MESSAGE_START..];
+    let end = text
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(text.len());
+
+    TestReport {
+        status,
+        message: String::from_utf8_lossy(&text[..end]).trim().to_owned(),
+        frames,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_test_dir, run_test_rom, TestStatus, DEFAULT_MAX_FRAMES};
+    use crate::{core::Cartridge, test::nrom_with_program, Nes};
+    use std::path::Path;
+
+    /// Directory searched for test ROMs, override with `NES_TEST_ROMS`
+    const TEST_ROMS: &str = "test_data/roms";
+
+    /// NROM image that asks for a reset once, then reports `result` with a message
+    fn nes(result: u8) -> Nes {
+        #[rustfmt::skip]
+        let code = [
+            // write the signature and "ok" with a running status
+            0xA9, 0xDE, 0x8D, 0x01, 0x60, 0xA9, 0xB0, 0x8D, 0x02, 0x60, 0xA9, 0x61, 0x8D, 0x03,
+            0x60, 0xA9, 0x6F, 0x8D, 0x04, 0x60, 0xA9, 0x6B, 0x8D, 0x05, 0x60, 0xA9, 0x80, 0x8D,
+            0x00, 0x60,
+            // the first time through, $10 is clear: set it and ask for a reset
+            0xA5, 0x10, 0xD0, 0x09, 0xE6, 0x10, 0xA9, 0x81, 0x8D, 0x00, 0x60, 0xD0, 0xFE,
+            // after the reset, report the result and wait
+            0xA9, result, 0x8D, 0x00, 0x60, 0x4C, 0x30, 0x80,
+        ];
+
+        nrom_with_program(&code)
+    }
+
+    #[test]
+    fn results_are_reported_after_a_requested_reset() {
+        let report = run_test_rom(&mut nes(0), 60).unwrap();
+        assert_eq!(report.status, TestStatus::Passed);
+        assert_eq!(report.message, "ok");
+
+        let report = run_test_rom(&mut nes(3), 60).unwrap();
+        assert_eq!(report.status, TestStatus::Failed(3));
+    }
+
+    #[test]
+    fn roms_without_the_protocol_are_detected() {
+        let cartridge = Cartridge::try_from(Path::new("test_data/nestest.nes")).unwrap();
+        let mut nes = Nes::builder().cartridge(cartridge).build().unwrap();
+
+        assert_eq!(
+            run_test_rom(&mut nes, DEFAULT_MAX_FRAMES).unwrap().status,
+            TestStatus::NoProtocol
+        );
+    }
+
+    /// Runs every ROM in the local test ROM directory, which isn't checked in. ROMs
+    /// that never write the signature count as failures. Only the default directory
+    /// may be missing, one named by `NES_TEST_ROMS` has to exist.
+    #[test]
+    fn local_test_roms_pass() {
+        let dir = match std::env::var("NES_TEST_ROMS") {
+            Ok(dir) => {
+                assert!(
+                    Path::new(&dir).is_dir(),
+                    "NES_TEST_ROMS={dir} isn't a directory"
+                );
+                dir
+            }
+            Err(_) if Path::new(TEST_ROMS).is_dir() => TEST_ROMS.to_owned(),
+            Err(_) => return,
+        };
+
+        let failures: Vec<_> = run_test_dir(&dir, DEFAULT_MAX_FRAMES)
+            .unwrap()
+            .into_iter()
+            .filter_map(|(path, report)| match report {
+                Ok(report) if !report.status.is_failure() => None,
+                Ok(report) => Some(format!(
+                    "{}: {:?} {}",
+                    path.display(),
+                    report.status,
+                    report.message
+                )),
+                Err(error) => Some(format!("{}: {error}", path.display())),
+            })
+            .collect();
+
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+}
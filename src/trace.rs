@@ -8,7 +8,7 @@ use crate::{
 };
 
 pub fn trace(cpu: &mut Cpu) -> Result<String> {
-    let opcode_map: &OpCodeMap = &(*OPCODE_MAP);
+    let opcode_map: &OpCodeMap = &OPCODE_MAP;
 
     let program_counter = cpu.program_counter.get();
     let code = cpu.read_byte(program_counter)?;